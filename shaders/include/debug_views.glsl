@@ -0,0 +1,21 @@
+// 0 draws textures, the rest are debug views: 1 normals, 2 texture coordinates, 3 depth
+uniform int debug_view;
+// clip planes, to turn depth back into a distance
+uniform float near;
+uniform float far;
+
+vec4 debug_colour(vec4 colour, vec3 world_position, vec2 texture_coordinate) {
+    if (debug_view == 1) {
+        // faces are flat, so the normal comes straight from how the position changes across the face
+        vec3 normal = normalize(cross(dFdx(world_position), dFdy(world_position)));
+        return vec4(normal * 0.5 + 0.5, 1.0);
+    } else if (debug_view == 2) {
+        return vec4(fract(texture_coordinate), 0.0, 1.0);
+    } else if (debug_view == 3) {
+        float ndc_depth = gl_FragCoord.z * 2.0 - 1.0;
+        float distance = (2.0 * near * far) / (far + near - ndc_depth * (far - near));
+        return vec4(vec3(distance / far), 1.0);
+    }
+
+    return colour;
+}
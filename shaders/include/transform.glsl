@@ -0,0 +1,4 @@
+// places a model in the world and projects it onto the screen
+uniform mat4 model;
+uniform mat4 view;
+uniform mat4 projection;
@@ -0,0 +1,9 @@
+#version 330 core
+
+layout (location = 0) in vec3 position;
+
+#include "include/transform.glsl"
+
+void main() {
+    gl_Position = projection * view * model * vec4(position, 1.0);
+}
@@ -0,0 +1,24 @@
+#version 330 core
+
+// draws each triangle's normal as a line sticking out of its middle
+layout (triangles) in;
+layout (line_strip, max_vertices = 2) out;
+
+in vec3 world_position[];
+
+#include "include/transform.glsl"
+
+// in blocks
+const float NORMAL_LENGTH = 0.4;
+
+void main() {
+    // triangles wind anticlockwise seen from the front
+    vec3 normal = normalize(cross(world_position[1] - world_position[0], world_position[2] - world_position[0]));
+    vec3 centre = (world_position[0] + world_position[1] + world_position[2]) / 3.0;
+
+    gl_Position = projection * view * vec4(centre, 1.0);
+    EmitVertex();
+    gl_Position = projection * view * vec4(centre + normal * NORMAL_LENGTH, 1.0);
+    EmitVertex();
+    EndPrimitive();
+}
@@ -0,0 +1,12 @@
+#version 330 core
+
+layout (location = 0) in vec3 position;
+
+out vec3 world_position;
+
+#include "include/transform.glsl"
+
+void main() {
+    // projected in the geometry shader, once the normal is known
+    world_position = (model * vec4(position, 1.0)).xyz;
+}
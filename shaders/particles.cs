@@ -0,0 +1,30 @@
+#version 430 core
+
+// WORK_GROUP_SIZE is defined by the program that builds this shader
+layout (local_size_x = WORK_GROUP_SIZE) in;
+
+struct Particle {
+    // w is the time left to live in seconds, dead particles have none
+    vec4 position;
+    vec4 velocity;
+};
+
+layout (std430, binding = 0) buffer Particles {
+    Particle particles[];
+};
+
+uniform float delta_time;
+uniform float gravity;
+
+void main() {
+    uint index = gl_GlobalInvocationID.x;
+    if (index >= uint(particles.length()) || particles[index].position.w <= 0.0) {
+        return;
+    }
+
+    Particle particle = particles[index];
+    particle.velocity.y -= gravity * delta_time;
+    particle.position.xyz += particle.velocity.xyz * delta_time;
+    particle.position.w -= delta_time;
+    particles[index] = particle;
+}
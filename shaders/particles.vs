@@ -0,0 +1,16 @@
+#version 330 core
+
+// the storage buffer the compute shader writes, read back as vertices
+layout (location = 0) in vec4 position;
+
+#include "include/transform.glsl"
+
+void main() {
+    // dead particles are put outside the clip volume
+    if (position.w <= 0.0) {
+        gl_Position = vec4(2.0, 2.0, 2.0, 1.0);
+        return;
+    }
+
+    gl_Position = projection * view * model * vec4(position.xyz, 1.0);
+}
@@ -3,10 +3,30 @@
 out vec4 FragColour;
 
 in vec2 texture_coordinate;
+flat in vec4 texture_region;
+in vec3 world_position;
 
-//texture sampler
-uniform sampler2D texture1;
+//texture sampler, every block texture packed into one atlas
+uniform sampler2D atlas;
+
+// defined when debug views are turned on in the settings
+#ifdef DEBUG_VIEWS
+#include "include/debug_views.glsl"
+#endif
 
 void main() {
-    FragColour = texture(texture1, texture_coordinate); // 0.2 returns 80% first input colour, 20% the second.
-}
\ No newline at end of file
+    // texture coordinates count blocks, wrap them into this face's part of the atlas
+    vec2 uv = texture_region.xy + fract(texture_coordinate) * texture_region.zw;
+    vec4 colour = texture(atlas, uv);
+
+    // see-through parts of transparent blocks
+    if (colour.a < 0.5) {
+        discard;
+    }
+
+#ifdef DEBUG_VIEWS
+    colour = debug_colour(colour, world_position, texture_coordinate);
+#endif
+
+    FragColour = colour;
+}
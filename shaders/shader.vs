@@ -2,14 +2,18 @@
 
 layout (location = 0) in vec3 position;
 layout (location = 1) in vec2 texture;
+layout (location = 2) in vec4 atlas_region;
 
 out vec2 texture_coordinate;
+flat out vec4 texture_region;
+out vec3 world_position;
 
-uniform mat4 model;
-uniform mat4 view;
-uniform mat4 projection;
+#include "include/transform.glsl"
 
 void main() {
-    gl_Position = projection * view * model * vec4(position, 1.0);
+    vec4 world = model * vec4(position, 1.0);
+    gl_Position = projection * view * world;
     texture_coordinate = vec2(texture.x, texture.y);
-}
\ No newline at end of file
+    texture_region = atlas_region;
+    world_position = world.xyz;
+}
@@ -0,0 +1,189 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use image::{Rgba, RgbaImage};
+use crate::error::Error;
+
+// pixels of each image's edge repeated around it so filtering doesn't bleed into its neighbours
+const PADDING : u32 = 4;
+
+/// Where one image ended up in the atlas, in texture coordinates (0..1 across the whole atlas).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AtlasRegion {
+    pub u : f32,
+    pub v : f32,
+    pub width : f32,
+    pub height : f32,
+}
+
+/// All block textures packed into a single image.
+pub struct Atlas {
+    pub image : RgbaImage,
+    regions : HashMap<String, AtlasRegion>,
+}
+
+impl Atlas {
+    /// Packs every image in `directory`, each one named after its file name without the extension.
+    pub fn load_directory(directory : &Path) -> Result<Self, Error> {
+        let mut paths: Vec<_> = fs::read_dir(directory)
+            .map_err(|error| Error::Io { path: directory.to_path_buf(), error })?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.is_file())
+            .collect();
+        // directory order isn't stable, keep the layout the same between runs
+        paths.sort();
+
+        let images = paths
+            .iter()
+            .map(|path| {
+                let image = image::open(path).map_err(|error| Error::Image { path: path.clone(), error })?;
+                Ok((texture_name(path), image.to_rgba()))
+            })
+            .collect::<Result<_, Error>>()?;
+
+        Ok(Atlas::pack(images))
+    }
+
+    /// Shelf packing: images are sorted tallest first and laid out in rows.
+    pub fn pack(mut images : Vec<(String, RgbaImage)>) -> Self {
+        images.sort_by(|a, b| b.1.height().cmp(&a.1.height()).then_with(|| a.0.cmp(&b.0)));
+
+        let padded = |image : &RgbaImage| (image.width() + 2 * PADDING, image.height() + 2 * PADDING);
+        let area: u32 = images.iter().map(|(_, image)| { let (w, h) = padded(image); w * h }).sum();
+        let widest = images.iter().map(|(_, image)| padded(image).0).max().unwrap_or(1);
+        let width = widest.max((area as f64).sqrt().ceil() as u32).next_power_of_two();
+
+        // place every image, starting a new shelf when the current one is full
+        let mut placements = Vec::with_capacity(images.len());
+        let (mut x, mut y, mut shelf_height) = (0, 0, 0);
+        for (_, image) in &images {
+            let (w, h) = padded(image);
+            if x + w > width {
+                x = 0;
+                y += shelf_height;
+                shelf_height = 0;
+            }
+
+            placements.push((x + PADDING, y + PADDING));
+            x += w;
+            shelf_height = shelf_height.max(h);
+        }
+        let height = (y + shelf_height).max(1).next_power_of_two();
+
+        let mut atlas = RgbaImage::new(width, height);
+        let mut regions = HashMap::new();
+        for ((name, image), (left, top)) in images.iter().zip(placements) {
+            blit_padded(&mut atlas, image, left, top);
+
+            regions.insert(name.clone(), AtlasRegion {
+                u: left as f32 / width as f32,
+                v: top as f32 / height as f32,
+                width: image.width() as f32 / width as f32,
+                height: image.height() as f32 / height as f32,
+            });
+        }
+
+        Atlas {
+            image: atlas,
+            regions,
+        }
+    }
+
+    pub fn region(&self, name : &str) -> Option<AtlasRegion> {
+        self.regions.get(name).copied()
+    }
+}
+
+fn texture_name(path : &Path) -> String {
+    path.file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+// copy the image in, then repeat its outermost pixels into the padding around it
+fn blit_padded(atlas : &mut RgbaImage, image : &RgbaImage, left : u32, top : u32) {
+    let (width, height) = (image.width() as i64, image.height() as i64);
+    let padding = PADDING as i64;
+
+    for y in -padding..height + padding {
+        for x in -padding..width + padding {
+            let source: Rgba<u8> = *image.get_pixel(
+                x.clamp(0, width - 1) as u32,
+                y.clamp(0, height - 1) as u32,
+            );
+            atlas.put_pixel((left as i64 + x) as u32, (top as i64 + y) as u32, source);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn solid_image(width : u32, height : u32, colour : [u8; 4]) -> RgbaImage {
+        let mut image = RgbaImage::new(width, height);
+        for pixel in image.pixels_mut() {
+            *pixel = Rgba(colour);
+        }
+        image
+    }
+
+    fn sample() -> Atlas {
+        Atlas::pack(vec![
+            ("red".to_string(), solid_image(64, 64, [255, 0, 0, 255])),
+            ("green".to_string(), solid_image(32, 16, [0, 255, 0, 255])),
+            ("blue".to_string(), solid_image(100, 40, [0, 0, 255, 255])),
+        ])
+    }
+
+    #[test]
+    fn atlas_is_power_of_two() {
+        let atlas = sample();
+
+        assert!(atlas.image.width().is_power_of_two());
+        assert!(atlas.image.height().is_power_of_two());
+    }
+
+    #[test]
+    fn regions_hold_their_image() {
+        let atlas = sample();
+        let (width, height) = (atlas.image.width() as f32, atlas.image.height() as f32);
+
+        for (name, colour) in [("red", [255, 0, 0, 255]), ("green", [0, 255, 0, 255]), ("blue", [0, 0, 255, 255])] {
+            let region = atlas.region(name).unwrap();
+
+            // every corner of the region, and the padding just outside it, has the image's colour
+            for (u, v) in [(0.0, 0.0), (1.0, 0.0), (0.0, 1.0), (1.0, 1.0)] {
+                let (au, av) = (region.u + u * region.width, region.v + v * region.height);
+                let x = (au * width).round() as i64 - (u == 1.0) as i64;
+                let y = (av * height).round() as i64 - (v == 1.0) as i64;
+                let outside_x = x + if u == 1.0 { 1 } else { -1 };
+                let outside_y = y + if v == 1.0 { 1 } else { -1 };
+
+                assert_eq!(atlas.image.get_pixel(x as u32, y as u32).data, colour);
+                assert_eq!(atlas.image.get_pixel(outside_x as u32, outside_y as u32).data, colour);
+            }
+        }
+    }
+
+    #[test]
+    fn regions_do_not_overlap() {
+        let atlas = sample();
+        let regions: Vec<AtlasRegion> = ["red", "green", "blue"].iter().map(|name| atlas.region(name).unwrap()).collect();
+
+        for (i, a) in regions.iter().enumerate() {
+            assert!(a.u >= 0.0 && a.v >= 0.0 && a.u + a.width <= 1.0 && a.v + a.height <= 1.0);
+
+            for b in &regions[i + 1..] {
+                let separate = a.u + a.width <= b.u || b.u + b.width <= a.u
+                    || a.v + a.height <= b.v || b.v + b.height <= a.v;
+                assert!(separate);
+            }
+        }
+    }
+
+    #[test]
+    fn unknown_names_have_no_region() {
+        assert!(sample().region("purple").is_none());
+    }
+}
@@ -0,0 +1,280 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use glutin::event::{MouseButton, VirtualKeyCode};
+use crate::input::{Input, InputState};
+
+/// Things the player can do, independent of which key or button does them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    MoveForward,
+    MoveBackward,
+    MoveLeft,
+    MoveRight,
+    MoveUp,
+    MoveDown,
+    Jump,
+    CyclePolygonMode,
+    CycleDebugView,
+    ToggleChunkBorders,
+    ToggleNormals,
+    BreakBlock,
+    PlaceBlock,
+    ToggleCursorGrab,
+    ToggleFullscreen,
+    Quit,
+}
+
+impl Action {
+    pub const ALL : [Action; 16] = [
+        Action::MoveForward,
+        Action::MoveBackward,
+        Action::MoveLeft,
+        Action::MoveRight,
+        Action::MoveUp,
+        Action::MoveDown,
+        Action::Jump,
+        Action::CyclePolygonMode,
+        Action::CycleDebugView,
+        Action::ToggleChunkBorders,
+        Action::ToggleNormals,
+        Action::BreakBlock,
+        Action::PlaceBlock,
+        Action::ToggleCursorGrab,
+        Action::ToggleFullscreen,
+        Action::Quit,
+    ];
+
+    // the name used in the bindings file
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::MoveForward => "move_forward",
+            Action::MoveBackward => "move_backward",
+            Action::MoveLeft => "move_left",
+            Action::MoveRight => "move_right",
+            Action::MoveUp => "move_up",
+            Action::MoveDown => "move_down",
+            Action::Jump => "jump",
+            Action::CyclePolygonMode => "cycle_polygon_mode",
+            Action::CycleDebugView => "cycle_debug_view",
+            Action::ToggleChunkBorders => "toggle_chunk_borders",
+            Action::ToggleNormals => "toggle_normals",
+            Action::BreakBlock => "break_block",
+            Action::PlaceBlock => "place_block",
+            Action::ToggleCursorGrab => "toggle_cursor_grab",
+            Action::ToggleFullscreen => "toggle_fullscreen",
+            Action::Quit => "quit",
+        }
+    }
+
+    fn from_name(name : &str) -> Option<Action> {
+        Action::ALL.iter().copied().find(|action| action.name() == name)
+    }
+
+    /// Held actions are polled every frame, the rest fire once when their key goes down.
+    pub fn is_held(self) -> bool {
+        matches!(self,
+            Action::MoveForward | Action::MoveBackward | Action::MoveLeft | Action::MoveRight
+            | Action::MoveUp | Action::MoveDown | Action::Jump)
+    }
+}
+
+/// Which inputs trigger each action. Loaded from a text file of `action = key, key` lines,
+/// any action the file doesn't mention keeps its default keys.
+pub struct Bindings {
+    bindings : HashMap<Action, Vec<Input>>,
+}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        use VirtualKeyCode::*;
+
+        let defaults = [
+            (Action::MoveForward, vec![Input::Key(W)]),
+            (Action::MoveBackward, vec![Input::Key(S)]),
+            (Action::MoveLeft, vec![Input::Key(A)]),
+            (Action::MoveRight, vec![Input::Key(D)]),
+            (Action::MoveUp, vec![Input::Key(E)]),
+            (Action::MoveDown, vec![Input::Key(Q), Input::Key(LShift)]),
+            (Action::Jump, vec![Input::Key(Space)]),
+            (Action::CyclePolygonMode, vec![Input::Key(F)]),
+            (Action::CycleDebugView, vec![Input::Key(F3)]),
+            (Action::ToggleChunkBorders, vec![Input::Key(F4)]),
+            (Action::ToggleNormals, vec![Input::Key(F5)]),
+            (Action::BreakBlock, vec![Input::Mouse(MouseButton::Left)]),
+            (Action::PlaceBlock, vec![Input::Mouse(MouseButton::Right)]),
+            (Action::ToggleCursorGrab, vec![Input::Key(Tab)]),
+            (Action::ToggleFullscreen, vec![Input::Key(F11)]),
+            (Action::Quit, vec![Input::Key(Escape)]),
+        ];
+
+        Bindings {
+            bindings: defaults.into_iter().collect(),
+        }
+    }
+}
+
+impl Bindings {
+    /// Reads the bindings file, falling back to the defaults if it doesn't exist.
+    /// Lines that can't be understood are reported and skipped.
+    pub fn load(path : &Path) -> Self {
+        let Ok(text) = fs::read_to_string(path) else {
+            return Bindings::default();
+        };
+
+        let (bindings, errors) = Bindings::parse(&text);
+        for error in errors {
+            println!("{}: {}", path.display(), error);
+        }
+
+        bindings
+    }
+
+    pub fn parse(text : &str) -> (Self, Vec<String>) {
+        let mut bindings = Bindings::default();
+        let mut errors = Vec::new();
+
+        for (number, line) in text.lines().enumerate() {
+            // everything after a # is a comment
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let Some((name, inputs)) = line.split_once('=') else {
+                errors.push(format!("line {}: expected `action = key`", number + 1));
+                continue;
+            };
+
+            let Some(action) = Action::from_name(name.trim()) else {
+                errors.push(format!("line {}: unknown action '{}'", number + 1, name.trim()));
+                continue;
+            };
+
+            let inputs: Result<Vec<Input>, String> = inputs
+                .split(',')
+                .map(str::trim)
+                .filter(|input| !input.is_empty())
+                .map(|input| parse_input(input).ok_or_else(|| format!("line {}: unknown key '{}'", number + 1, input)))
+                .collect();
+
+            match inputs {
+                Ok(inputs) => { bindings.bindings.insert(action, inputs); }
+                Err(error) => errors.push(error),
+            }
+        }
+
+        (bindings, errors)
+    }
+
+    /// Whether any input bound to the action is held down.
+    pub fn is_active(&self, action : Action, input : &InputState) -> bool {
+        self.inputs(action).iter().any(|&bound| input.is_down(bound))
+    }
+
+    /// Actions bound to an input, an input can be shared by several actions.
+    pub fn actions_for(&self, input : Input) -> impl Iterator<Item = Action> + '_ {
+        Action::ALL.into_iter().filter(move |&action| self.inputs(action).contains(&input))
+    }
+
+    fn inputs(&self, action : Action) -> &[Input] {
+        self.bindings.get(&action).map(Vec::as_slice).unwrap_or(&[])
+    }
+}
+
+// key names are the same as VirtualKeyCode's variants, mouse buttons are MouseLeft, MouseRight and MouseMiddle
+fn parse_input(name : &str) -> Option<Input> {
+    match name {
+        "MouseLeft" => return Some(Input::Mouse(MouseButton::Left)),
+        "MouseRight" => return Some(Input::Mouse(MouseButton::Right)),
+        "MouseMiddle" => return Some(Input::Mouse(MouseButton::Middle)),
+        _ => {}
+    }
+
+    KEYS.iter()
+        .find(|key| format!("{:?}", key) == name)
+        .map(|&key| Input::Key(key))
+}
+
+// keys that can be named in the bindings file
+const KEYS : [VirtualKeyCode; 98] = {
+    use VirtualKeyCode::*;
+    [
+        A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z,
+        Key0, Key1, Key2, Key3, Key4, Key5, Key6, Key7, Key8, Key9,
+        F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11, F12,
+        Escape, Space, Tab, Return, Back, Delete, Insert, Home, End, PageUp, PageDown,
+        Up, Down, Left, Right,
+        LShift, RShift, LControl, RControl, LAlt, RAlt,
+        Comma, Period, Semicolon, Apostrophe, Slash, Backslash, Minus, Equals, LBracket, RBracket, Grave,
+        Numpad0, Numpad1, Numpad2, Numpad3, Numpad4, Numpad5, Numpad6, Numpad7, Numpad8, Numpad9,
+        NumpadAdd, NumpadSubtract, NumpadMultiply, NumpadDivide, NumpadDecimal, NumpadComma, NumpadEnter, NumpadEquals,
+    ]
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn defaults_cover_every_action() {
+        let bindings = Bindings::default();
+
+        for action in Action::ALL {
+            assert!(!bindings.inputs(action).is_empty(), "{:?} has no default", action);
+        }
+    }
+
+    #[test]
+    fn file_overrides_only_what_it_mentions() {
+        let text = "
+            # ZQSD for AZERTY keyboards
+            move_forward = Z
+            move_left = Q, Left
+        ";
+        let (bindings, errors) = Bindings::parse(text);
+
+        assert!(errors.is_empty());
+        assert_eq!(bindings.inputs(Action::MoveForward), [Input::Key(VirtualKeyCode::Z)]);
+        assert_eq!(bindings.inputs(Action::MoveLeft), [Input::Key(VirtualKeyCode::Q), Input::Key(VirtualKeyCode::Left)]);
+        assert_eq!(bindings.inputs(Action::MoveRight), [Input::Key(VirtualKeyCode::D)]);
+    }
+
+    #[test]
+    fn punctuation_and_numpad_keys_can_be_bound() {
+        let (bindings, errors) = Bindings::parse("move_up = Minus, Numpad8\nmove_down = Grave, NumpadSubtract");
+
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(bindings.inputs(Action::MoveUp), [Input::Key(VirtualKeyCode::Minus), Input::Key(VirtualKeyCode::Numpad8)]);
+        assert_eq!(bindings.inputs(Action::MoveDown), [Input::Key(VirtualKeyCode::Grave), Input::Key(VirtualKeyCode::NumpadSubtract)]);
+    }
+
+    #[test]
+    fn mouse_buttons_can_be_bound() {
+        let (bindings, _) = Bindings::parse("place_block = MouseMiddle");
+
+        let actions: Vec<Action> = bindings.actions_for(Input::Mouse(MouseButton::Middle)).collect();
+        assert_eq!(actions, [Action::PlaceBlock]);
+    }
+
+    #[test]
+    fn bad_lines_are_reported_and_skipped() {
+        let (bindings, errors) = Bindings::parse("fly = F\nquit = NotAKey\njump\nmove_up = R");
+
+        assert_eq!(errors.len(), 3);
+        assert!(errors.iter().any(|error| error.contains("'NotAKey'")), "{:?}", errors);
+        assert_eq!(bindings.inputs(Action::Quit), [Input::Key(VirtualKeyCode::Escape)]);
+        assert_eq!(bindings.inputs(Action::MoveUp), [Input::Key(VirtualKeyCode::R)]);
+    }
+
+    #[test]
+    fn active_when_any_bound_input_is_held() {
+        let bindings = Bindings::default();
+        let mut input = InputState::default();
+        assert!(!bindings.is_active(Action::MoveDown, &input));
+
+        input.press(Input::Key(VirtualKeyCode::LShift));
+
+        assert!(bindings.is_active(Action::MoveDown, &input));
+    }
+}
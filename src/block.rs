@@ -0,0 +1,156 @@
+use crate::atlas::{Atlas, AtlasRegion};
+use crate::error::Error;
+
+// block ids stored in chunks, 0 is always empty space
+pub type BlockId = u8;
+
+pub const AIR : BlockId = 0;
+pub const BRICK : BlockId = 1;
+pub const WOOD : BlockId = 2;
+pub const SMILEY : BlockId = 3;
+pub const CRATE : BlockId = 4;
+
+/// Texture names (file names in resources/textures without the extension) for each side of a block.
+pub struct FaceTextures {
+    pub top : &'static str,
+    pub side : &'static str,
+    pub bottom : &'static str,
+}
+
+impl FaceTextures {
+    const fn all(name : &'static str) -> Self {
+        FaceTextures {
+            top: name,
+            side: name,
+            bottom: name,
+        }
+    }
+}
+
+pub struct BlockType {
+    pub name : &'static str,
+    pub solid : bool,
+    // transparent blocks don't hide the faces of blocks behind them
+    pub transparent : bool,
+    pub textures : FaceTextures,
+}
+
+// indexed by block id
+pub const BLOCK_TYPES : [BlockType; 5] = [
+    BlockType { name: "air", solid: false, transparent: true, textures: FaceTextures::all("") },
+    BlockType { name: "brick", solid: true, transparent: false, textures: FaceTextures::all("wall") },
+    BlockType { name: "wood", solid: true, transparent: false, textures: FaceTextures::all("wood_grain") },
+    BlockType { name: "smiley", solid: true, transparent: true, textures: FaceTextures::all("awesomeface") },
+    BlockType {
+        name: "crate",
+        solid: true,
+        transparent: false,
+        textures: FaceTextures { top: "wall", side: "wood_grain", bottom: "wall" },
+    },
+];
+
+/// Unknown ids are treated as air.
+pub fn block_type(block : BlockId) -> &'static BlockType {
+    BLOCK_TYPES.get(block as usize).unwrap_or(&BLOCK_TYPES[AIR as usize])
+}
+
+pub fn block_by_name(name : &str) -> Option<BlockId> {
+    BLOCK_TYPES.iter().position(|block_type| block_type.name == name).map(|id| id as BlockId)
+}
+
+pub fn is_solid(block : BlockId) -> bool {
+    block_type(block).solid
+}
+
+/// Whether a face of another block is hidden when this block is in front of it.
+pub fn is_opaque(block : BlockId) -> bool {
+    let block_type = block_type(block);
+    block_type.solid && !block_type.transparent
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Face {
+    Top,
+    Side,
+    Bottom,
+}
+
+/// Atlas regions for every face of every block type, looked up while meshing.
+pub struct BlockTextures {
+    regions : Vec<[AtlasRegion; 3]>,
+}
+
+impl BlockTextures {
+    pub fn new(atlas : &Atlas) -> Result<Self, Error> {
+        let region = |block_type : &BlockType, name : &'static str| {
+            atlas.region(name).ok_or(Error::MissingTexture { block: block_type.name, texture: name })
+        };
+
+        let regions = BLOCK_TYPES
+            .iter()
+            .map(|block_type| {
+                if !block_type.solid {
+                    // never drawn
+                    return Ok([FULL_REGION; 3]);
+                }
+
+                let textures = &block_type.textures;
+                Ok([
+                    region(block_type, textures.top)?,
+                    region(block_type, textures.side)?,
+                    region(block_type, textures.bottom)?,
+                ])
+            })
+            .collect::<Result<_, Error>>()?;
+
+        Ok(BlockTextures { regions })
+    }
+
+    pub fn region(&self, block : BlockId, face : Face) -> AtlasRegion {
+        let regions = self.regions.get(block as usize).unwrap_or(&self.regions[AIR as usize]);
+        regions[face as usize]
+    }
+
+    /// Every face maps to the whole texture, for tests that don't load any images.
+    #[cfg(test)]
+    pub fn untextured() -> Self {
+        BlockTextures {
+            regions: vec![[FULL_REGION; 3]; BLOCK_TYPES.len()],
+        }
+    }
+}
+
+const FULL_REGION : AtlasRegion = AtlasRegion {
+    u: 0.0,
+    v: 0.0,
+    width: 1.0,
+    height: 1.0,
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_block_texture_is_in_the_atlas() {
+        let atlas = Atlas::load_directory(std::path::Path::new("resources/textures")).unwrap();
+        let textures = BlockTextures::new(&atlas).unwrap();
+
+        assert_ne!(textures.region(CRATE, Face::Top), textures.region(CRATE, Face::Side));
+        assert_eq!(textures.region(BRICK, Face::Top), textures.region(BRICK, Face::Bottom));
+    }
+
+    #[test]
+    fn missing_textures_are_reported() {
+        let error = BlockTextures::new(&Atlas::pack(Vec::new())).err().unwrap();
+
+        assert!(matches!(error, Error::MissingTexture { block: "brick", texture: "wall" }));
+    }
+
+    #[test]
+    fn unknown_ids_are_air() {
+        assert!(!is_solid(200));
+        assert!(!is_opaque(AIR));
+        assert!(is_solid(SMILEY) && !is_opaque(SMILEY));
+    }
+}
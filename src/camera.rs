@@ -1,5 +1,4 @@
 //source : https://github.com/bwasty/learn-opengl-rs/blob/master/src/camera.rs
-use cgmath;
 use cgmath::vec3;
 use cgmath::prelude::*;
 
@@ -8,12 +7,15 @@ type Vector3 = cgmath::Vector3<f32>;
 type Matrix4 = cgmath::Matrix4<f32>;
 
 // Defines several possible options for camera movement. Used as abstraction to stay away from window-system specific input methods
+#[allow(non_camel_case_types, clippy::upper_case_acronyms)]
 #[derive(PartialEq, Clone, Copy)]
 pub enum Camera_Movement {
     FORWARD,
     BACKWARD,
     LEFT,
     RIGHT,
+    UP,
+    DOWN,
 }
 use self::Camera_Movement::*;
 
@@ -60,9 +62,10 @@ impl Default for Camera {
 }
 
 impl Camera {
-    /// Returns the view matrix calculated using Euler Angles and the LookAt Matrix
-    pub fn get_view_matrix(&self) -> Matrix4 {
-        Matrix4::look_at(self.position, self.position + self.front, self.up)
+    /// Returns the view matrix calculated using Euler Angles and the LookAt Matrix, with the camera at `position`.
+    /// The game draws the camera between its last two simulated positions, so this isn't always `self.position`.
+    pub fn get_view_matrix_at(&self, position: Point3) -> Matrix4 {
+        Matrix4::look_at_rh(position, position + self.front, self.up)
     }
 
     /// Processes input received from any keyboard-like input system. Accepts input parameter in the form of camera defined ENUM (to abstract it from windowing systems)
@@ -80,6 +83,13 @@ impl Camera {
         if direction == RIGHT {
             self.position += self.right * velocity;
         }
+        // straight up and down regardless of where the camera is looking
+        if direction == UP {
+            self.position += self.world_up * velocity;
+        }
+        if direction == DOWN {
+            self.position += -(self.world_up * velocity);
+        }
     }
 
     /// Processes input received from a mouse input system. Expects the offset value in both the x and y direction.
@@ -92,12 +102,7 @@ impl Camera {
 
         // Make sure that when pitch is out of bounds, screen doesn't get flipped
         if constrain_pitch {
-            if self.pitch > 89.0 {
-                self.pitch = 89.0;
-            }
-            if self.pitch < -89.0 {
-                self.pitch = -89.0;
-            }
+            self.pitch = self.pitch.clamp(-89.0, 89.0);
         }
 
         // Update front, right and up Vectors using the updated Eular angles
@@ -0,0 +1,91 @@
+use cgmath::Vector3;
+use crate::block::{BlockId, AIR};
+
+// chunks are cubes of CHUNK_SIZE blocks along each axis
+pub const CHUNK_SIZE : usize = 16;
+pub const CHUNK_VOLUME : usize = CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE;
+
+// order of the neighbours passed to the mesher: -x, +x, -y, +y, -z, +z
+pub const NEIGHBOUR_OFFSETS : [(i32, i32, i32); 6] = [
+    (-1, 0, 0), (1, 0, 0),
+    (0, -1, 0), (0, 1, 0),
+    (0, 0, -1), (0, 0, 1),
+];
+
+/// Position of a chunk in chunk coordinates (world block position / CHUNK_SIZE).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ChunkPos {
+    pub x : i32,
+    pub y : i32,
+    pub z : i32,
+}
+
+impl ChunkPos {
+    pub fn new(x : i32, y : i32, z : i32) -> Self {
+        ChunkPos { x, y, z }
+    }
+
+    /// The chunk containing the block at the given world position,
+    /// along with the block's position inside that chunk.
+    pub fn of_block(x : i32, y : i32, z : i32) -> (Self, (usize, usize, usize)) {
+        let size = CHUNK_SIZE as i32;
+        let chunk = ChunkPos::new(x.div_euclid(size), y.div_euclid(size), z.div_euclid(size));
+        let local = (
+            x.rem_euclid(size) as usize,
+            y.rem_euclid(size) as usize,
+            z.rem_euclid(size) as usize,
+        );
+
+        (chunk, local)
+    }
+
+    pub fn offset(&self, (dx, dy, dz) : (i32, i32, i32)) -> Self {
+        ChunkPos::new(self.x + dx, self.y + dy, self.z + dz)
+    }
+
+    /// World position of the chunk's (0, 0, 0) corner.
+    pub fn origin(&self) -> Vector3<f32> {
+        let size = CHUNK_SIZE as f32;
+        Vector3::new(self.x as f32 * size, self.y as f32 * size, self.z as f32 * size)
+    }
+}
+
+#[derive(Clone)]
+pub struct Chunk {
+    blocks : Box<[BlockId; CHUNK_VOLUME]>,
+}
+
+impl Default for Chunk {
+    fn default() -> Self {
+        Chunk {
+            blocks: Box::new([AIR; CHUNK_VOLUME]),
+        }
+    }
+}
+
+impl Chunk {
+    pub fn get(&self, x : usize, y : usize, z : usize) -> BlockId {
+        self.blocks[index(x, y, z)]
+    }
+
+    pub fn set(&mut self, x : usize, y : usize, z : usize, block : BlockId) {
+        self.blocks[index(x, y, z)] = block;
+    }
+
+    /// Every block in the chunk, in storage order.
+    pub fn blocks(&self) -> &[BlockId] {
+        &self.blocks[..]
+    }
+
+    /// Builds a chunk from blocks in the order returned by `blocks`.
+    pub fn from_blocks(blocks : &[BlockId]) -> Self {
+        let mut chunk = Chunk::default();
+        chunk.blocks.copy_from_slice(blocks);
+        chunk
+    }
+}
+
+fn index(x : usize, y : usize, z : usize) -> usize {
+    debug_assert!(x < CHUNK_SIZE && y < CHUNK_SIZE && z < CHUNK_SIZE);
+    (y * CHUNK_SIZE + z) * CHUNK_SIZE + x
+}
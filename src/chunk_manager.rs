@@ -0,0 +1,397 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use crate::block::BlockTextures;
+use crate::camera::Point3;
+use crate::chunk::{ChunkPos, CHUNK_SIZE, NEIGHBOUR_OFFSETS};
+use crate::jobs::{JobHandle, JobKind, JobOutput, JobResult, JobSystem};
+use crate::mesher::ChunkMesh;
+use crate::world::World;
+
+/// Where finished chunk meshes end up, the renderer keeps them on the GPU.
+pub trait ChunkMeshes {
+    /// Replaces whatever mesh `pos` had before.
+    fn upload_chunk_mesh(&mut self, pos : ChunkPos, mesh : &ChunkMesh);
+    fn free_chunk_mesh(&mut self, pos : ChunkPos);
+}
+
+/// Keeps the chunks around the camera loaded and meshed, and drops the ones that fall out of range.
+/// Generation and meshing run on the job system's worker threads, only GPU uploads happen here.
+pub struct ChunkManager {
+    // in chunks, measured horizontally from the camera's chunk
+    view_distance : i32,
+    max_jobs_in_flight : usize,
+    max_uploads_per_frame : usize,
+    jobs : JobSystem,
+    centre : Option<ChunkPos>,
+    // chunks that should be loaded but aren't yet, closest last
+    to_generate : Vec<ChunkPos>,
+    // loaded chunks whose mesh is missing or out of date
+    to_mesh : HashSet<ChunkPos>,
+    generating : HashMap<ChunkPos, JobHandle>,
+    meshing : HashMap<ChunkPos, JobHandle>,
+    // finished meshes waiting for their turn to be uploaded
+    ready : HashMap<ChunkPos, ChunkMesh>,
+}
+
+impl ChunkManager {
+    pub fn new(world : &World,
+               textures : Arc<BlockTextures>,
+               view_distance : i32,
+               max_jobs_in_flight : usize,
+               max_uploads_per_frame : usize) -> Self {
+        // leave a core for the render thread
+        let workers = std::thread::available_parallelism()
+            .map(|count| count.get().saturating_sub(1))
+            .unwrap_or(1);
+
+        ChunkManager {
+            view_distance,
+            max_jobs_in_flight,
+            max_uploads_per_frame,
+            jobs: JobSystem::new(workers, world.generator(), textures),
+            centre: None,
+            to_generate: Vec::new(),
+            to_mesh: HashSet::new(),
+            generating: HashMap::new(),
+            meshing: HashMap::new(),
+            ready: HashMap::new(),
+        }
+    }
+
+    // called once per frame from the game loop
+    pub fn update(&mut self, camera_position : Point3, world : &mut World, meshes : &mut impl ChunkMeshes) {
+        let size = CHUNK_SIZE as f32;
+        let centre = ChunkPos::new(
+            (camera_position.x / size).floor() as i32,
+            0,
+            (camera_position.z / size).floor() as i32,
+        );
+
+        if self.centre != Some(centre) {
+            self.centre = Some(centre);
+            self.refresh_resident_set(centre, world, meshes);
+        }
+
+        self.collect_results(world);
+        // meshing goes first, loaded chunks shouldn't stay invisible while far ones generate
+        self.schedule_meshing(centre, world);
+        self.schedule_generation(world);
+        self.upload_ready(centre, meshes);
+    }
+
+    /// Queues loaded chunks to be meshed again, e.g. after a block in them changed.
+    pub fn remesh(&mut self, chunks : &[ChunkPos]) {
+        self.to_mesh.extend(chunks.iter().copied());
+    }
+
+    fn is_in_range(&self, centre : ChunkPos, pos : ChunkPos) -> bool {
+        distance_squared(centre, pos) <= self.view_distance * self.view_distance
+    }
+
+    // work out which chunks should be resident around the new centre
+    fn refresh_resident_set(&mut self, centre : ChunkPos, world : &mut World, meshes : &mut impl ChunkMeshes) {
+        let unload: Vec<ChunkPos> = world.chunks
+            .keys()
+            .filter(|&&pos| !self.is_in_range(centre, pos))
+            .copied()
+            .collect();
+
+        for &pos in &unload {
+            world.unload(pos);
+            meshes.free_chunk_mesh(pos);
+            self.to_mesh.remove(&pos);
+            self.ready.remove(&pos);
+        }
+
+        // faces along the border were hidden by the chunk that's gone, the neighbours left behind need them back
+        for pos in unload {
+            for offset in NEIGHBOUR_OFFSETS {
+                let neighbour = pos.offset(offset);
+                if world.chunks.contains_key(&neighbour) {
+                    self.to_mesh.insert(neighbour);
+                }
+            }
+        }
+
+        // stop work on chunks that left view distance before their job finished
+        let out_of_range = |pos : &ChunkPos| distance_squared(centre, *pos) > self.view_distance * self.view_distance;
+        for jobs in [&mut self.generating, &mut self.meshing] {
+            jobs.retain(|pos, handle| {
+                let keep = !out_of_range(pos);
+                if !keep {
+                    handle.cancel();
+                }
+                keep
+            });
+        }
+
+        self.to_generate.clear();
+        for x in -self.view_distance..=self.view_distance {
+            for z in -self.view_distance..=self.view_distance {
+                for y in world.chunk_y_range() {
+                    let pos = ChunkPos::new(centre.x + x, y, centre.z + z);
+                    if self.is_in_range(centre, pos)
+                        && !world.chunks.contains_key(&pos)
+                        && !self.generating.contains_key(&pos) {
+                        self.to_generate.push(pos);
+                    }
+                }
+            }
+        }
+
+        // generate from the inside out, popping the closest chunk off the end
+        self.to_generate.sort_by_key(|pos| -distance_squared(centre, *pos));
+    }
+
+    fn jobs_in_flight(&self) -> usize {
+        self.generating.len() + self.meshing.len()
+    }
+
+    // only a limited number of jobs are queued so newly needed chunks don't wait behind stale ones
+    fn schedule_generation(&mut self, world : &mut World) {
+        while self.jobs_in_flight() < self.max_jobs_in_flight {
+            let Some(pos) = self.to_generate.pop() else {
+                break;
+            };
+
+            // saved chunks are loaded straight away, there's nothing to generate
+            if world.take_stored(pos) {
+                self.chunk_loaded(pos, world);
+                continue;
+            }
+
+            let handle = self.jobs.submit(pos, JobKind::Generate);
+            self.generating.insert(pos, handle);
+        }
+    }
+
+    // shares the job limit with generation, whatever doesn't fit waits in `to_mesh` for a later frame
+    fn schedule_meshing(&mut self, centre : ChunkPos, world : &World) {
+        let mut pending: Vec<ChunkPos> = self.to_mesh.iter().copied().collect();
+        pending.sort_by_key(|pos| -distance_squared(centre, *pos));
+
+        while self.jobs_in_flight() < self.max_jobs_in_flight {
+            let Some(pos) = pending.pop() else {
+                break;
+            };
+            self.to_mesh.remove(&pos);
+
+            let Some(chunk) = world.chunks.get(&pos) else {
+                continue;
+            };
+
+            // a mesh already being built for this chunk is out of date now
+            if let Some(old) = self.meshing.remove(&pos) {
+                old.cancel();
+            }
+
+            let neighbours = world.neighbours(pos).map(|neighbour| neighbour.cloned());
+            let handle = self.jobs.submit(pos, JobKind::Mesh { chunk: chunk.clone(), neighbours });
+            self.meshing.insert(pos, handle);
+        }
+    }
+
+    fn collect_results(&mut self, world : &mut World) {
+        let results: Vec<JobResult> = self.jobs.completed().collect();
+
+        for result in results {
+            match result.output {
+                JobOutput::Chunk(chunk) => {
+                    // results for cancelled or superseded jobs are dropped
+                    if self.generating.get(&result.pos).map(|handle| handle.id) != Some(result.id) {
+                        continue;
+                    }
+                    self.generating.remove(&result.pos);
+
+                    world.chunks.insert(result.pos, chunk);
+                    self.chunk_loaded(result.pos, world);
+                }
+                JobOutput::Mesh(mesh) => {
+                    if self.meshing.get(&result.pos).map(|handle| handle.id) != Some(result.id) {
+                        continue;
+                    }
+                    self.meshing.remove(&result.pos);
+
+                    self.ready.insert(result.pos, mesh);
+                }
+            }
+        }
+    }
+
+    fn chunk_loaded(&mut self, pos : ChunkPos, world : &World) {
+        self.to_mesh.insert(pos);
+
+        // faces along the shared border may now be hidden
+        for offset in NEIGHBOUR_OFFSETS {
+            let neighbour = pos.offset(offset);
+            if world.chunks.contains_key(&neighbour) {
+                self.to_mesh.insert(neighbour);
+            }
+        }
+    }
+
+    fn upload_ready(&mut self, centre : ChunkPos, meshes : &mut impl ChunkMeshes) {
+        let mut ready: Vec<ChunkPos> = self.ready.keys().copied().collect();
+        ready.sort_by_key(|pos| distance_squared(centre, *pos));
+
+        for pos in ready.into_iter().take(self.max_uploads_per_frame) {
+            if let Some(mesh) = self.ready.remove(&pos) {
+                meshes.upload_chunk_mesh(pos, &mesh);
+            }
+        }
+    }
+}
+
+// horizontal distance, the whole column around the camera matters equally
+fn distance_squared(centre : ChunkPos, pos : ChunkPos) -> i32 {
+    let dx = pos.x - centre.x;
+    let dz = pos.z - centre.z;
+    dx * dx + dz * dz
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+    use crate::chunk::Chunk;
+    use crate::terrain::WorldType;
+
+    // stands in for the renderer, remembers what it was asked to do
+    #[derive(Default)]
+    struct Recorder {
+        uploaded : Vec<ChunkPos>,
+        freed : Vec<ChunkPos>,
+    }
+
+    impl ChunkMeshes for Recorder {
+        fn upload_chunk_mesh(&mut self, pos : ChunkPos, _mesh : &ChunkMesh) {
+            self.uploaded.push(pos);
+        }
+
+        fn free_chunk_mesh(&mut self, pos : ChunkPos) {
+            self.freed.push(pos);
+        }
+    }
+
+    fn manager(world : &World, view_distance : i32, max_jobs_in_flight : usize, max_uploads_per_frame : usize) -> ChunkManager {
+        ChunkManager::new(
+            world,
+            Arc::new(BlockTextures::untextured()),
+            view_distance,
+            max_jobs_in_flight,
+            max_uploads_per_frame
+        )
+    }
+
+    fn empty_mesh() -> ChunkMesh {
+        ChunkMesh { vertices: Vec::new(), quad_count: 0 }
+    }
+
+    #[test]
+    fn uploads_stay_within_the_budget_closest_first() {
+        let world = World::new(WorldType::Demo);
+        let mut manager = manager(&world, 4, 4, 2);
+        let centre = ChunkPos::new(0, 0, 0);
+        for x in [3, 1, 2, 0] {
+            manager.ready.insert(ChunkPos::new(x, 0, 0), empty_mesh());
+        }
+
+        let mut meshes = Recorder::default();
+        manager.upload_ready(centre, &mut meshes);
+
+        assert_eq!(meshes.uploaded, vec![ChunkPos::new(0, 0, 0), ChunkPos::new(1, 0, 0)]);
+        assert_eq!(manager.ready.len(), 2);
+    }
+
+    #[test]
+    fn meshing_respects_the_job_limit() {
+        let mut world = World::new(WorldType::Demo);
+        let mut manager = manager(&world, 8, 2, 4);
+        let centre = ChunkPos::new(0, 0, 0);
+        let chunks: Vec<ChunkPos> = (0..5).map(|x| ChunkPos::new(x, 0, 0)).collect();
+        for &pos in &chunks {
+            world.chunks.insert(pos, Chunk::default());
+        }
+
+        manager.remesh(&chunks);
+        manager.schedule_meshing(centre, &world);
+
+        assert_eq!(manager.jobs_in_flight(), 2);
+        assert!(manager.meshing.contains_key(&chunks[0]) && manager.meshing.contains_key(&chunks[1]));
+        assert_eq!(manager.to_mesh.len(), 3);
+
+        // nothing else starts until a job finishes
+        manager.schedule_meshing(centre, &world);
+        assert_eq!(manager.jobs_in_flight(), 2);
+    }
+
+    #[test]
+    fn chunks_out_of_range_are_unloaded_and_freed() {
+        let mut world = World::new(WorldType::Demo);
+        let mut manager = manager(&world, 2, 4, 4);
+        let centre = ChunkPos::new(0, 0, 0);
+        let near = ChunkPos::new(0, 0, 0);
+        let far = ChunkPos::new(5, 0, 0);
+        world.chunks.insert(near, Chunk::default());
+        world.chunks.insert(far, Chunk::default());
+        manager.ready.insert(far, empty_mesh());
+
+        let mut meshes = Recorder::default();
+        manager.refresh_resident_set(centre, &mut world, &mut meshes);
+
+        assert!(world.chunks.contains_key(&near));
+        assert!(!world.chunks.contains_key(&far));
+        assert_eq!(meshes.freed, vec![far]);
+        assert!(manager.ready.is_empty());
+
+        // loaded chunks aren't generated again, and the closest missing one comes first
+        assert!(!manager.to_generate.contains(&near));
+        assert!(manager.to_generate.iter().all(|&pos| manager.is_in_range(centre, pos)));
+        assert_eq!(distance_squared(centre, *manager.to_generate.last().unwrap()), 0);
+    }
+
+    #[test]
+    fn neighbours_of_unloaded_chunks_are_remeshed() {
+        let mut world = World::new(WorldType::Demo);
+        let mut manager = manager(&world, 2, 4, 4);
+        let edge = ChunkPos::new(2, 0, 0);
+        let gone = ChunkPos::new(3, 0, 0);
+        world.chunks.insert(edge, Chunk::default());
+        world.chunks.insert(gone, Chunk::default());
+
+        manager.refresh_resident_set(ChunkPos::new(0, 0, 0), &mut world, &mut Recorder::default());
+
+        // the chunk at the edge of view lost the neighbour that hid its border faces
+        assert!(manager.to_mesh.contains(&edge));
+        assert!(!manager.to_mesh.contains(&gone));
+    }
+
+    #[test]
+    fn everything_in_view_gets_loaded_and_uploaded() {
+        let mut world = World::new(WorldType::Demo);
+        let mut manager = manager(&world, 1, 3, 2);
+        let mut meshes = Recorder::default();
+        let camera = Point3::new(0.5, 0.0, 0.5);
+
+        // the camera's column and its four neighbours
+        let expected = 5 * world.chunk_y_range().len();
+        let deadline = Instant::now() + Duration::from_secs(10);
+
+        while world.chunks.len() < expected || !manager.ready.is_empty() || manager.jobs_in_flight() > 0
+            || !manager.to_mesh.is_empty() {
+            assert!(Instant::now() < deadline, "chunks never finished loading");
+
+            let uploaded = meshes.uploaded.len();
+            manager.update(camera, &mut world, &mut meshes);
+            assert!(meshes.uploaded.len() - uploaded <= 2);
+            assert!(manager.jobs_in_flight() <= 3);
+
+            std::thread::sleep(Duration::from_millis(1));
+        }
+
+        for pos in world.chunks.keys() {
+            assert!(meshes.uploaded.contains(pos), "{:?} was never uploaded", pos);
+        }
+        assert!(meshes.freed.is_empty());
+    }
+}
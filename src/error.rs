@@ -0,0 +1,88 @@
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+use crate::settings::SettingsError;
+
+/// Anything that can stop the game from starting up: a bad window or GL context,
+/// or an asset that can't be loaded.
+#[derive(Debug)]
+pub enum Error {
+    Settings(SettingsError),
+    /// A file or directory the game needs couldn't be read.
+    Io { path : PathBuf, error : io::Error },
+    Image { path : PathBuf, error : image::ImageError },
+    /// A block type names a texture that isn't in the texture directory.
+    MissingTexture { block : &'static str, texture : &'static str },
+    /// The atlas is bigger than the GL implementation can hold in one texture.
+    TextureTooLarge { width : u32, height : u32, max : u32 },
+    /// A bad `#include`, found before the source ever reaches the GLSL compiler.
+    ShaderPreprocess { path : PathBuf, line : usize, message : String },
+    /// The log has each message pointed at the file and line it's about.
+    ShaderCompile { path : PathBuf, log : String },
+    ShaderLink { paths : Vec<PathBuf>, log : String },
+    /// The stages can't make a program together.
+    InvalidProgram { paths : Vec<PathBuf>, message : String },
+    /// The vertex shader's inputs don't match the vertices it's drawn with.
+    VertexLayout { paths : Vec<PathBuf>, message : String },
+    /// Something the driver's OpenGL version doesn't have, `feature` is plural.
+    Unsupported { feature : &'static str, version : &'static str },
+    Window(glutin::CreationError),
+    Context(glutin::ContextError),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Settings(error) => write!(f, "{}", error),
+            Error::Io { path, error } => write!(f, "couldn't read {}: {}", path.display(), error),
+            Error::Image { path, error } => write!(f, "couldn't load image {}: {}", path.display(), error),
+            Error::MissingTexture { block, texture } => {
+                write!(f, "block '{}' uses texture '{}', which isn't in the texture directory", block, texture)
+            }
+            Error::TextureTooLarge { width, height, max } => write!(
+                f,
+                "texture atlas is {}x{} but the largest texture this GPU supports is {}x{}",
+                width, height, max, max
+            ),
+            Error::ShaderPreprocess { path, line, message } => write!(f, "{}:{}: {}", path.display(), line, message),
+            Error::ShaderCompile { path, log } => {
+                write!(f, "couldn't compile shader {}:\n{}", path.display(), log.trim_end())
+            }
+            Error::ShaderLink { paths, log } => {
+                let names: Vec<String> = paths.iter().map(|path| path.display().to_string()).collect();
+                write!(f, "couldn't link shader program ({}):\n{}", names.join(", "), log.trim_end())
+            }
+            Error::InvalidProgram { paths, message } => {
+                let names: Vec<String> = paths.iter().map(|path| path.display().to_string()).collect();
+                write!(f, "can't build shader program ({}): {}", names.join(", "), message)
+            }
+            Error::VertexLayout { paths, message } => {
+                let names: Vec<String> = paths.iter().map(|path| path.display().to_string()).collect();
+                write!(f, "shader program ({}) doesn't match its vertex layout: {}", names.join(", "), message)
+            }
+            Error::Unsupported { feature, version } => write!(f, "{} need OpenGL {}", feature, version),
+            Error::Window(error) => write!(f, "couldn't create the window: {}", error),
+            Error::Context(error) => write!(f, "couldn't set up the OpenGL context: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<SettingsError> for Error {
+    fn from(error : SettingsError) -> Self {
+        Error::Settings(error)
+    }
+}
+
+impl From<glutin::CreationError> for Error {
+    fn from(error : glutin::CreationError) -> Self {
+        Error::Window(error)
+    }
+}
+
+impl From<glutin::ContextError> for Error {
+    fn from(error : glutin::ContextError) -> Self {
+        Error::Context(error)
+    }
+}
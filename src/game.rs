@@ -1,69 +1,257 @@
-use cgmath::{Deg, InnerSpace, Matrix4, perspective, vec3, Vector3};
+use cgmath::{Deg, Matrix4, perspective};
+use std::sync::Arc;
+use std::time::Instant;
+use glutin::event::Event;
 use glutin::event_loop::{ControlFlow, EventLoop};
-use crate::game_specs::{WINDOW_HEIGHT, WINDOW_WIDTH};
+use crate::atlas::Atlas;
+use crate::block::{is_solid, BlockTextures, AIR};
+use crate::camera::{Camera, Camera_Movement::*};
+use crate::chunk::CHUNK_SIZE;
+use crate::chunk_manager::ChunkManager;
+use crate::bindings::{Action, Bindings};
+use crate::error::Error;
 use crate::game_window::GameWindow;
+use crate::input::InputState;
+use crate::raycast::{raycast, RaycastHit};
 use crate::renderer::Renderer;
+use crate::save::save_exists;
+use crate::settings::Settings;
+use crate::timing::{FixedTimestep, FrameLimiter};
 use crate::world::World;
 
-pub struct Game { }
+const NEAR_PLANE : f32 = 0.1;
+
+pub struct Game {
+    settings : Settings,
+}
 
 impl Game {
-    pub fn new() -> Self {
-        Game { }
+    pub fn new(settings : Settings) -> Self {
+        Game { settings }
     }
 
-    pub fn run(&self) {
+    /// Sets up the window, world and renderer, then runs until the window closes.
+    /// Only returns if setting up fails.
+    pub fn run(self) -> Result<(), Error> {
+        let settings = self.settings;
+
         // Initialize the event loop and window builder
         //the event loop handles events such as keyboard and mouse input, window resizing, and more.
         let event_loop = EventLoop::new();
-        let mut window = GameWindow::new();
+        let mut window = GameWindow::new(&event_loop, &settings, Bindings::load(&settings.assets.bindings))?;
 
         // Initialize OpenGL (make opengl functions available within the program)
         gl::load_with(|symbol| window.context.get_proc_address(symbol) as *const _);
 
-        let world = World::new();
-        let test_cube_pos = Vector3::new(0.0, 0.0, 0.0);
+        // None when there's a save we couldn't read, so this session doesn't overwrite it
+        let mut save_directory = Some(settings.world.save_directory.clone());
+        let mut world = if save_exists(&settings.world.save_directory) {
+            World::load(&settings.world.save_directory).unwrap_or_else(|error| {
+                println!("Couldn't load the saved world, starting a new one that won't be saved: {}", error);
+                save_directory = None;
+                World::new(settings.world_type())
+            })
+        } else {
+            World::new(settings.world_type())
+        };
+        window.camera.position = world.spawn_point();
+
+        // every block texture is packed into one atlas, the mesher needs to know where each one went
+        let atlas = Atlas::load_directory(&settings.assets.texture_directory)?;
+        let block_textures = Arc::new(BlockTextures::new(&atlas)?);
+
+        // anything past the loaded chunks would be clipped anyway
+        let far_plane = ((settings.world.view_distance + 1) * CHUNK_SIZE as i32) as f32;
+
+        let mut renderer = Renderer::new(&atlas, &settings, NEAR_PLANE, far_plane)?;
+        renderer.init_renderer();
 
-        let mut renderer = Renderer::new();
-        renderer.init_renderer(world);
+        let mut chunk_manager = ChunkManager::new(
+            &world,
+            block_textures,
+            settings.world.view_distance,
+            settings.world.max_chunk_jobs_in_flight,
+            settings.world.max_chunk_uploads_per_frame
+        );
 
-        // Initialize variables for tracking time
-        let mut last_frame_time = std::time::Instant::now();
-        let mut delta_time = std::time::Duration::new(0, 0);
+        // the simulation runs in fixed steps, drawing happens as often as the frame limiter allows
+        let mut timestep = FixedTimestep::new(settings.timing.tick_rate);
+        let mut frame_limiter = FrameLimiter::new(settings.timing.max_fps, settings.window.vsync, Instant::now());
+        let mut last_frame_time = Instant::now();
+        // where the camera was before the latest step, frames are drawn between the two positions
+        let mut previous_position = window.camera.position;
 
         // Main event loop runs until application is terminated.
         event_loop.run(move |event, _, control_flow| {
-            *control_flow = ControlFlow::Poll;
+            match event {
+                Event::LoopDestroyed => {
+                    if let Some(save_directory) = &save_directory {
+                        if let Err(error) = world.save(save_directory) {
+                            println!("Failed to save the world: {}", error);
+                        }
+                    }
+                }
+
+                // every pending event has been handled, run one frame if it's due
+                Event::MainEventsCleared => {
+                    let now = Instant::now();
+                    if !frame_limiter.start_frame(now) {
+                        return;
+                    }
+
+                    //calculate time between frames
+                    let elapsed = now.duration_since(last_frame_time);
+                    last_frame_time = now;
+
+                    // looking around stays tied to the frame rate so it feels immediate
+                    window.update_mouse_look(elapsed.as_secs_f32());
+
+                    for action in window.take_actions() {
+                        match action {
+                            Action::BreakBlock | Action::PlaceBlock => {
+                                if let Some(broken) = edit_world(action, &settings, &window.camera, &mut world, &mut chunk_manager) {
+                                    renderer.spawn_block_particles(broken);
+                                }
+                            }
+                            Action::CyclePolygonMode => println!("Polygon mode: {:?}", renderer.cycle_polygon_mode()),
+                            Action::CycleDebugView => match renderer.cycle_debug_view() {
+                                Some(debug_view) => println!("Debug view: {:?}", debug_view),
+                                None => println!("Debug views are turned off in the settings"),
+                            },
+                            Action::ToggleChunkBorders => println!("Chunk borders: {}", renderer.toggle_chunk_borders()),
+                            Action::ToggleNormals => println!("Normals: {}", renderer.toggle_normals()),
+                            Action::ToggleCursorGrab => window.set_cursor_grab(!window.cursor_grabbed),
+                            Action::ToggleFullscreen => {
+                                let mode = window.mode().next();
+                                window.set_mode(mode);
+
+                                if let Err(error) = settings.save_window_mode(mode) {
+                                    println!("Couldn't save the window mode: {}", error);
+                                }
+                            }
+                            Action::Quit => {
+                                // the first Escape frees the mouse, the next one quits
+                                if window.cursor_grabbed {
+                                    window.set_cursor_grab(false);
+                                } else {
+                                    *control_flow = ControlFlow::Exit;
+                                }
+                            }
+                            // held actions are handled by apply_movement
+                            _ => {}
+                        }
+                    }
 
-            //calculate time between frames
-            let current_frame_time = std::time::Instant::now();
-            delta_time = current_frame_time.duration_since(last_frame_time);
-            last_frame_time = current_frame_time;
+                    // simulate
+                    for _ in 0..timestep.advance(elapsed) {
+                        previous_position = window.camera.position;
+                        apply_movement(&window.bindings, &window.input, &mut window.camera, timestep.step_seconds());
+                        renderer.update_particles(timestep.step_seconds());
+                    }
 
-            // Convert delta_time to seconds as a floating-point number
-            let delta_time = delta_time.as_secs() as f32 + delta_time.subsec_nanos() as f32 / 1_000_000_000.0;
+                    // stream chunks in and out around the camera
+                    chunk_manager.update(window.camera.position, &mut world, &mut renderer);
 
-            // events
-            window.process_events(event, delta_time, control_flow);
+                    if settings.assets.hot_reload_shaders {
+                        renderer.reload_shaders();
+                    }
 
-            let projection: Matrix4<f32> = perspective(
-                Deg(window.camera.zoom),
-                WINDOW_WIDTH as f32 / WINDOW_HEIGHT as f32,
-                0.1,
-                100.0
-            );
+                    if let Some(size) = window.take_resize() {
+                        renderer.resize(size.width, size.height);
+                    }
 
-            let view: Matrix4<f32> = window.camera.get_view_matrix();
+                    let projection: Matrix4<f32> = perspective(
+                        Deg(window.camera.zoom),
+                        window.aspect_ratio(),
+                        NEAR_PLANE,
+                        far_plane
+                    );
 
-            let mut model: Matrix4<f32> = Matrix4::from_translation(test_cube_pos); //TODO
-            let angle = 20.0;
-            model = model * Matrix4::from_axis_angle(vec3(1.0, 0.0, 0.0).normalize(), Deg(angle));
+                    let position = previous_position + (window.camera.position - previous_position) * timestep.alpha();
+                    let view: Matrix4<f32> = window.camera.get_view_matrix_at(position);
 
-            // render
-            renderer.render(projection, view, model);
+                    // outline whatever block the camera is pointing at
+                    let target = target_block(&settings, &window.camera, &world).map(|hit| hit.block);
 
+                    // render
+                    renderer.render(projection, view, target);
 
-            window.context.swap_buffers().unwrap();
+                    if let Err(error) = window.context.swap_buffers() {
+                        // the context is gone (e.g. the GPU was reset), there's nothing left to draw with
+                        println!("Couldn't present the frame: {}", error);
+                        *control_flow = ControlFlow::Exit;
+                    }
+
+                    // sleep until the next frame is due instead of spinning
+                    if *control_flow != ControlFlow::Exit {
+                        *control_flow = match frame_limiter.wake_time() {
+                            Some(wake_time) => ControlFlow::WaitUntil(wake_time),
+                            // vsync blocks in swap_buffers, so polling doesn't spin
+                            None => ControlFlow::Poll,
+                        };
+                    }
+                }
+
+                // events
+                event => window.process_events(event, control_flow),
+            }
         });
     }
-}
\ No newline at end of file
+}
+
+// move for every held direction at once, so diagonals work and movement doesn't wait on key repeat
+fn apply_movement(bindings : &Bindings, input : &InputState, camera : &mut Camera, delta_time : f32) {
+    let directions = [
+        (Action::MoveForward, FORWARD),
+        (Action::MoveBackward, BACKWARD),
+        (Action::MoveLeft, LEFT),
+        (Action::MoveRight, RIGHT),
+        (Action::MoveUp, UP),
+        // there's no gravity yet, so jumping just flies upwards
+        (Action::Jump, UP),
+        (Action::MoveDown, DOWN),
+    ];
+
+    for (action, direction) in directions {
+        if bindings.is_active(action, input) {
+            camera.process_keyboard(direction, delta_time);
+        }
+    }
+}
+
+// the block the camera is looking at, if it's within reach
+fn target_block(settings : &Settings, camera : &Camera, world : &World) -> Option<RaycastHit> {
+    raycast(
+        camera.position,
+        camera.front,
+        settings.world.block_reach,
+        |x, y, z| is_solid(world.get_block(x, y, z))
+    )
+}
+
+// break or place the block the camera is looking at, returns the block that was broken if one was
+fn edit_world(action : Action,
+              settings : &Settings,
+              camera : &Camera,
+              world : &mut World,
+              chunk_manager : &mut ChunkManager) -> Option<[i32; 3]> {
+    let hit = target_block(settings, camera, world)?;
+
+    let (affected, broken) = match action {
+        Action::BreakBlock => (world.set_block(hit.block[0], hit.block[1], hit.block[2], AIR), Some(hit.block)),
+        Action::PlaceBlock => {
+            let [x, y, z] = hit.adjacent();
+            let camera_block = camera.position.map(|coordinate| coordinate.floor() as i32);
+
+            // don't bury the camera, and there's no face to place against from inside a block
+            if hit.normal == [0, 0, 0] || [x, y, z] == [camera_block.x, camera_block.y, camera_block.z] {
+                return None;
+            }
+            (world.set_block(x, y, z, settings.placed_block()), None)
+        }
+        _ => return None,
+    };
+
+    chunk_manager.remesh(&affected);
+    broken
+}
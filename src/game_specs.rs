@@ -1,10 +0,0 @@
-use glutin_opengl_demo::PolygonMode;
-use glutin_opengl_demo::PolygonMode::*;
-
-pub const TITLE : &str = "OpenGL Demo";
-
-// settings
-pub const WINDOW_WIDTH : u32 = 800;
-pub const WINDOW_HEIGHT : u32 = 600;
-
-pub const POLYGON_MODE : PolygonMode = Line;
\ No newline at end of file
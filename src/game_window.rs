@@ -1,130 +1,326 @@
-use crate::game_specs::*;
-
 use glutin::{ContextBuilder, ContextWrapper, PossiblyCurrent};
-use glutin::dpi::LogicalSize;
-use glutin::event::{ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent};
+use glutin::dpi::{LogicalSize, PhysicalSize};
+use glutin::event::{DeviceEvent, ElementState, Event, KeyboardInput, WindowEvent};
 use glutin::event_loop::{ControlFlow, EventLoop};
-use glutin::window::{Window, WindowBuilder};
-use crate::camera::{Camera, Camera_Movement::*, Point3};
+use glutin::monitor::{MonitorHandle, VideoMode};
+use glutin::window::{Fullscreen, Window, WindowBuilder};
+use serde::Deserialize;
+use crate::camera::{Camera, Point3};
+use crate::bindings::{Action, Bindings};
+use crate::error::Error;
+use crate::input::{Input, InputState, MouseLook};
+use crate::settings::Settings;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WindowMode {
+    Windowed,
+    /// A window without decorations covering the whole monitor.
+    Borderless,
+    /// Exclusive fullscreen, switching the monitor's video mode.
+    Fullscreen,
+}
+
+impl WindowMode {
+    /// The mode after this one when cycling with the fullscreen key.
+    pub fn next(self) -> WindowMode {
+        match self {
+            WindowMode::Windowed => WindowMode::Borderless,
+            WindowMode::Borderless => WindowMode::Fullscreen,
+            WindowMode::Fullscreen => WindowMode::Windowed,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            WindowMode::Windowed => "windowed",
+            WindowMode::Borderless => "borderless",
+            WindowMode::Fullscreen => "fullscreen",
+        }
+    }
+}
 
 pub struct GameWindow {
-    event_loop : EventLoop<()>,
     pub context : ContextWrapper<PossiblyCurrent, Window>,
     pub camera : Camera,
-    last_frame_time: std::time::Instant,
-    first_mouse : bool,
-    last_x : f32,
-    last_y : f32,
+    pub input : InputState,
+    pub bindings : Bindings,
+    // while grabbed the cursor is hidden and locked to the window, and moving the mouse looks around
+    pub cursor_grabbed : bool,
+    // one-off actions triggered since the game loop last asked
+    actions : Vec<Action>,
+    mouse_look : MouseLook,
+    // drawable size in physical pixels, which is larger than the logical size on HiDPI screens
+    size : PhysicalSize<u32>,
+    resized : bool,
+    mode : WindowMode,
+    // video mode wanted for exclusive fullscreen, the monitor's best one when None
+    fullscreen_resolution : Option<[u32; 2]>,
 }
 
 impl GameWindow {
-    pub fn new() -> Self {
-        let event_loop = EventLoop::new();
+    pub fn new(event_loop : &EventLoop<()>, settings : &Settings, bindings : Bindings) -> Result<Self, Error> {
+        let monitor = event_loop
+            .available_monitors()
+            .nth(settings.window.monitor)
+            .or_else(|| event_loop.primary_monitor());
+        let fullscreen = monitor.and_then(|monitor| {
+            fullscreen_for(settings.window.mode, monitor, settings.window.fullscreen_resolution)
+        });
 
         let window = WindowBuilder::new()
-            .with_title(TITLE)
-            .with_inner_size(LogicalSize::new(WINDOW_WIDTH, WINDOW_HEIGHT));
+            .with_title(settings.window.title.as_str())
+            .with_inner_size(LogicalSize::new(settings.window.width, settings.window.height))
+            .with_fullscreen(fullscreen);
 
-        let context = unsafe {
-            ContextBuilder::new()
-                .build_windowed(window, &event_loop)
-                .unwrap()
-                .make_current()
-        }.unwrap();
+        let context = ContextBuilder::new()
+            .with_vsync(settings.window.vsync)
+            .build_windowed(window, event_loop)?;
+        let context = unsafe { context.make_current() }.map_err(|(_, error)| error)?;
 
         let camera = Camera {
             position: Point3::new(0.0, 0.0, 3.0),
+            zoom: settings.camera.fov,
+            mouse_sensitivity: settings.camera.mouse_sensitivity,
+            movement_speed: settings.camera.movement_speed,
             ..Camera::default()
         };
 
-        let mut first_mouse = true;
-        let mut last_x: f32 = WINDOW_WIDTH as f32 / 2.0;
-        let mut last_y: f32 = WINDOW_HEIGHT as f32 / 2.0;
+        let mouse_look = MouseLook::new(settings.camera.mouse_smoothing, settings.camera.invert_y);
+        let size = context.window().inner_size();
 
-        GameWindow {
-            event_loop,
+        let mut game_window = GameWindow {
             context,
             camera,
-            last_frame_time: std::time::Instant::now(),
-            first_mouse,
-            last_x,
-            last_y,
+            input: InputState::default(),
+            bindings,
+            cursor_grabbed: false,
+            actions: Vec::new(),
+            mouse_look,
+            size,
+            // so the renderer sets its viewport on the first frame
+            resized: true,
+            mode: settings.window.mode,
+            fullscreen_resolution: settings.window.fullscreen_resolution,
+        };
+        game_window.set_cursor_grab(true);
+
+        Ok(game_window)
+    }
+
+    pub fn set_cursor_grab(&mut self, grab : bool) {
+        let window = self.context.window();
+        if let Err(error) = window.set_cursor_grab(grab) {
+            println!("Couldn't {} the cursor: {}", if grab { "grab" } else { "release" }, error);
         }
+        window.set_cursor_visible(!grab);
+
+        self.cursor_grabbed = grab;
+        self.mouse_look.reset();
     }
-    pub fn process_events(&mut self,
-                          event : Event<()>,
-                          delta_time : f32,
-                          control_flow : &mut ControlFlow) {
+
+    /// Turns the camera by the mouse motion since the last frame.
+    pub fn update_mouse_look(&mut self, delta_time : f32) {
+        let (xoffset, yoffset) = self.mouse_look.take_offset(delta_time);
+        self.camera.process_mouse_movement(xoffset, yoffset, true);
+    }
+
+    /// Width over height of the drawable area, for the projection matrix.
+    pub fn aspect_ratio(&self) -> f32 {
+        // a minimised window can have no height at all
+        self.size.width as f32 / self.size.height.max(1) as f32
+    }
+
+    /// The new drawable size if the window changed size since the last call.
+    pub fn take_resize(&mut self) -> Option<PhysicalSize<u32>> {
+        if std::mem::take(&mut self.resized) {
+            Some(self.size)
+        } else {
+            None
+        }
+    }
+
+    fn resize(&mut self, size : PhysicalSize<u32>) {
+        // the GL surface doesn't follow the window by itself on every platform
+        self.context.resize(size);
+        self.size = size;
+        self.resized = true;
+    }
+
+    pub fn mode(&self) -> WindowMode {
+        self.mode
+    }
+
+    /// Switches the window to `mode` on whichever monitor it's currently on.
+    pub fn set_mode(&mut self, mode : WindowMode) {
+        let window = self.context.window();
+        let fullscreen = window
+            .current_monitor()
+            .or_else(|| window.primary_monitor())
+            .and_then(|monitor| fullscreen_for(mode, monitor, self.fullscreen_resolution));
+
+        window.set_fullscreen(fullscreen);
+        self.mode = mode;
+    }
+
+    pub fn process_events(&mut self, event : Event<()>, control_flow : &mut ControlFlow) {
         match event {
+            // raw motion keeps coming when the cursor is against the edge of the window, unlike CursorMoved
+            Event::DeviceEvent { event: DeviceEvent::MouseMotion { delta: (dx, dy) }, .. } if self.cursor_grabbed => {
+                self.mouse_look.add_motion(dx, dy);
+            }
+
             Event::WindowEvent { event, .. } => match event {
                 WindowEvent::CloseRequested => {
                     *control_flow = ControlFlow::Exit;
                 }
 
-                WindowEvent::KeyboardInput { input, .. } => {
-                    self.process_key_input(input, delta_time);
+                WindowEvent::Resized(size) => {
+                    self.resize(size);
                 }
 
-                WindowEvent::CursorMoved { position, .. } => {
-                    let xpos = position.x as f32;
-                    let ypos = position.y as f32;
-
-                    if self.first_mouse {
-                        self.last_x = xpos;
-                        self.last_y = ypos;
-                        self.first_mouse = false;
-                    }
+                // moving to a screen with a different DPI changes the physical size without a Resized event
+                WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
+                    self.resize(*new_inner_size);
+                }
 
-                    let xoffset = xpos - self.last_x;
-                    let yoffset = self.last_y - ypos; // reversed since y-coordinates go from bottom to top
+                WindowEvent::KeyboardInput { input, .. } => {
+                    self.process_key_input(input);
+                }
 
-                    self.last_x = xpos;
-                    self.last_y = ypos;
+                // releases that happen while another window has focus never reach us
+                WindowEvent::Focused(false) => {
+                    self.input.clear();
+                    self.set_cursor_grab(false);
+                }
 
-                    self.camera.process_mouse_movement(xoffset, yoffset, true);
+                WindowEvent::MouseInput { state, button, .. } => {
+                    self.process_input(Input::Mouse(button), state);
                 }
 
+                //This is a catch-all case in the match statement like finally in switch
                 _ => {}
-            }
+            },
 
-            //This is a catch-all case in the match statement like finally in switch
-            _ => (),
+            _ => {}
         }
     }
 
-    pub fn process_key_input(&mut self, input : KeyboardInput, delta_time : f32) {
+    /// One-off actions triggered since the last call, in the order they happened.
+    pub fn take_actions(&mut self) -> Vec<Action> {
+        std::mem::take(&mut self.actions)
+    }
+
+    pub fn process_key_input(&mut self, input : KeyboardInput) {
         if let Some(key_code) = input.virtual_keycode {
-            match key_code {
-                VirtualKeyCode::Escape => {
-                    if input.state == ElementState::Pressed {
-                        // Set the window to close when Escape key is pressed.
-                        // Note: You'll need to handle window closing separately in your event loop.
-                        // For example, you can set the control flow to ControlFlow::Exit.
-                        // control_flow = ControlFlow::Exit;
-                    }
-                }
-                VirtualKeyCode::W => {
-                    if input.state == ElementState::Pressed {
-                        self.camera.process_keyboard(FORWARD, delta_time);
-                    }
-                }
-                VirtualKeyCode::S => {
-                    if input.state == ElementState::Pressed {
-                        self.camera.process_keyboard(BACKWARD, delta_time);
-                    }
-                }
-                VirtualKeyCode::A => {
-                    if input.state == ElementState::Pressed {
-                        self.camera.process_keyboard(LEFT, delta_time);
-                    }
-                }
-                VirtualKeyCode::D => {
-                    if input.state == ElementState::Pressed {
-                        self.camera.process_keyboard(RIGHT, delta_time);
-                    }
+            self.process_input(Input::Key(key_code), input.state);
+        }
+    }
+
+    // held actions are polled from the input state each frame, the rest are queued when their input goes down
+    fn process_input(&mut self, input : Input, state : ElementState) {
+        match state {
+            // clicking while the cursor is free only grabs it again, it shouldn't also break a block
+            ElementState::Pressed if !self.cursor_grabbed && matches!(input, Input::Mouse(_)) => {
+                self.set_cursor_grab(true);
+            }
+            ElementState::Pressed => {
+                // key repeat sends more presses while the key is held, only the first one counts
+                if !self.input.is_down(input) {
+                    let fired = self.bindings.actions_for(input).filter(|action| !action.is_held());
+                    self.actions.extend(fired);
                 }
-                _ => {}
+                self.input.press(input);
+            }
+            ElementState::Released => self.input.release(input),
+        }
+    }
+}
+
+fn fullscreen_for(mode : WindowMode, monitor : MonitorHandle, resolution : Option<[u32; 2]>) -> Option<Fullscreen> {
+    match mode {
+        WindowMode::Windowed => None,
+        WindowMode::Borderless => Some(Fullscreen::Borderless(Some(monitor))),
+        WindowMode::Fullscreen => {
+            let modes: Vec<VideoMode> = monitor.video_modes().collect();
+            let details: Vec<VideoModeDetails> = modes
+                .iter()
+                .map(|mode| VideoModeDetails {
+                    width: mode.size().width,
+                    height: mode.size().height,
+                    refresh_rate: mode.refresh_rate(),
+                    bit_depth: mode.bit_depth(),
+                })
+                .collect();
+
+            match choose_video_mode(&details, resolution) {
+                Some(index) => Some(Fullscreen::Exclusive(modes[index].clone())),
+                // some platforms don't list any modes, borderless is the closest thing
+                None => Some(Fullscreen::Borderless(Some(monitor))),
             }
         }
     }
 }
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct VideoModeDetails {
+    width : u32,
+    height : u32,
+    refresh_rate : u16,
+    bit_depth : u16,
+}
+
+// the requested resolution if the monitor has it, otherwise the largest one,
+// picking the highest refresh rate and bit depth among equal sizes
+fn choose_video_mode(modes : &[VideoModeDetails], resolution : Option<[u32; 2]>) -> Option<usize> {
+    let rank = |mode : &VideoModeDetails| {
+        let matches = resolution.is_some_and(|[width, height]| mode.width == width && mode.height == height);
+        (matches, mode.width * mode.height, mode.refresh_rate, mode.bit_depth)
+    };
+
+    (0..modes.len()).max_by_key(|&index| rank(&modes[index]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mode(width : u32, height : u32, refresh_rate : u16) -> VideoModeDetails {
+        VideoModeDetails { width, height, refresh_rate, bit_depth: 32 }
+    }
+
+    #[test]
+    fn picks_the_largest_fastest_mode_by_default() {
+        let modes = [mode(1280, 720, 60), mode(1920, 1080, 60), mode(1920, 1080, 144), mode(800, 600, 75)];
+
+        assert_eq!(choose_video_mode(&modes, None), Some(2));
+    }
+
+    #[test]
+    fn picks_the_requested_resolution() {
+        let modes = [mode(1280, 720, 60), mode(1920, 1080, 144), mode(1280, 720, 120)];
+
+        assert_eq!(choose_video_mode(&modes, Some([1280, 720])), Some(2));
+    }
+
+    #[test]
+    fn unavailable_resolution_falls_back_to_the_largest() {
+        let modes = [mode(1280, 720, 60), mode(1920, 1080, 60)];
+
+        assert_eq!(choose_video_mode(&modes, Some([3840, 2160])), Some(1));
+        assert_eq!(choose_video_mode(&[], None), None);
+    }
+
+    #[test]
+    fn cycling_visits_every_mode() {
+        let mut mode = WindowMode::Windowed;
+        let mut seen = Vec::new();
+        for _ in 0..3 {
+            seen.push(mode);
+            mode = mode.next();
+        }
+
+        assert_eq!(mode, WindowMode::Windowed);
+        assert_eq!(seen, [WindowMode::Windowed, WindowMode::Borderless, WindowMode::Fullscreen]);
+    }
+}
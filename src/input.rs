@@ -0,0 +1,163 @@
+use std::collections::HashSet;
+use glutin::event::{MouseButton, VirtualKeyCode};
+
+/// A key or mouse button.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Input {
+    Key(VirtualKeyCode),
+    Mouse(MouseButton),
+}
+
+/// Which keys and mouse buttons are currently held down, kept up to date from window events
+/// so the game loop can poll them every frame instead of waiting for key repeats.
+#[derive(Default)]
+pub struct InputState {
+    down : HashSet<Input>,
+}
+
+impl InputState {
+    pub fn press(&mut self, input : Input) {
+        self.down.insert(input);
+    }
+
+    pub fn release(&mut self, input : Input) {
+        self.down.remove(&input);
+    }
+
+    pub fn is_down(&self, input : Input) -> bool {
+        self.down.contains(&input)
+    }
+
+    /// Forget everything that's held, used when the window loses focus and won't see the releases.
+    pub fn clear(&mut self) {
+        self.down.clear();
+    }
+}
+
+/// Turns raw mouse motion into camera look offsets once per frame.
+pub struct MouseLook {
+    // share of the motion still held back after a 60th of a second, 0 applies it straight away
+    smoothing : f32,
+    invert_y : bool,
+    // motion received since the last frame
+    pending : (f32, f32),
+    smoothed : (f32, f32),
+}
+
+impl MouseLook {
+    pub fn new(smoothing : f32, invert_y : bool) -> Self {
+        MouseLook {
+            smoothing,
+            invert_y,
+            pending: (0.0, 0.0),
+            smoothed: (0.0, 0.0),
+        }
+    }
+
+    pub fn add_motion(&mut self, dx : f64, dy : f64) {
+        self.pending.0 += dx as f32;
+        self.pending.1 += dy as f32;
+    }
+
+    /// The x and y offsets to turn the camera by this frame, y is positive for looking up.
+    /// `delta_time` is the frame time in seconds, so the smoothing feels the same at any frame rate.
+    pub fn take_offset(&mut self, delta_time : f32) -> (f32, f32) {
+        let (dx, dy) = std::mem::take(&mut self.pending);
+
+        let keep = self.smoothing.powf(delta_time * 60.0);
+        self.smoothed = (
+            self.smoothed.0 * keep + dx * (1.0 - keep),
+            self.smoothed.1 * keep + dy * (1.0 - keep),
+        );
+
+        // mouse motion is positive downwards, pitch is positive upwards
+        let y = if self.invert_y { self.smoothed.1 } else { -self.smoothed.1 };
+        (self.smoothed.0, y)
+    }
+
+    /// Drops any motion still being smoothed out, e.g. when the cursor is released.
+    pub fn reset(&mut self) {
+        self.pending = (0.0, 0.0);
+        self.smoothed = (0.0, 0.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the frame rate the smoothing setting is tuned for
+    const FRAME : f32 = 1.0 / 60.0;
+
+    #[test]
+    fn keys_stay_down_until_released() {
+        let mut input = InputState::default();
+        let w = Input::Key(VirtualKeyCode::W);
+
+        input.press(w);
+        input.press(Input::Key(VirtualKeyCode::A));
+        assert!(input.is_down(w));
+        assert!(input.is_down(Input::Key(VirtualKeyCode::A)));
+
+        input.release(w);
+        assert!(!input.is_down(w));
+        assert!(input.is_down(Input::Key(VirtualKeyCode::A)));
+    }
+
+    #[test]
+    fn clear_releases_everything() {
+        let mut input = InputState::default();
+        input.press(Input::Key(VirtualKeyCode::S));
+        input.press(Input::Mouse(MouseButton::Left));
+
+        input.clear();
+
+        assert!(!input.is_down(Input::Key(VirtualKeyCode::S)));
+        assert!(!input.is_down(Input::Mouse(MouseButton::Left)));
+    }
+
+    #[test]
+    fn unsmoothed_motion_is_applied_in_one_frame() {
+        let mut look = MouseLook::new(0.0, false);
+        look.add_motion(3.0, 1.0);
+        look.add_motion(2.0, 1.0);
+
+        assert_eq!(look.take_offset(FRAME), (5.0, -2.0));
+        assert_eq!(look.take_offset(FRAME), (0.0, 0.0));
+    }
+
+    #[test]
+    fn invert_y_flips_vertical_motion() {
+        let mut look = MouseLook::new(0.0, true);
+        look.add_motion(0.0, 4.0);
+
+        assert_eq!(look.take_offset(FRAME), (0.0, 4.0));
+    }
+
+    #[test]
+    fn smoothing_spreads_motion_over_frames() {
+        let mut look = MouseLook::new(0.5, false);
+        look.add_motion(8.0, 0.0);
+
+        let first = look.take_offset(FRAME).0;
+        let rest: f32 = (0..30).map(|_| look.take_offset(FRAME).0).sum();
+
+        assert!((first - 4.0).abs() < 1e-3);
+        assert!((first + rest - 8.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn smoothing_keeps_pace_at_any_frame_rate() {
+        let mut slow = MouseLook::new(0.5, false);
+        let mut fast = MouseLook::new(0.5, false);
+        slow.add_motion(8.0, 0.0);
+        fast.add_motion(8.0, 0.0);
+
+        // a 30 fps frame covers as much as two 60 fps ones
+        let slow_turn = slow.take_offset(2.0 * FRAME).0;
+        let fast_turn = fast.take_offset(FRAME).0 + fast.take_offset(FRAME).0;
+
+        assert!((slow_turn - 6.0).abs() < 1e-3);
+        assert!((fast_turn - 6.0).abs() < 1e-3);
+    }
+}
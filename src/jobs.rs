@@ -0,0 +1,220 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use crate::block::BlockTextures;
+use crate::chunk::{Chunk, ChunkPos};
+use crate::mesher::{mesh_chunk, ChunkMesh};
+use crate::terrain::WorldGenerator;
+
+pub enum JobKind {
+    Generate,
+    /// Meshing works on copies so the world can keep changing on the main thread.
+    Mesh { chunk : Chunk, neighbours : [Option<Chunk>; 6] },
+}
+
+pub enum JobOutput {
+    Chunk(Chunk),
+    Mesh(ChunkMesh),
+}
+
+struct Job {
+    id : u64,
+    pos : ChunkPos,
+    cancelled : Arc<AtomicBool>,
+    kind : JobKind,
+}
+
+pub struct JobResult {
+    pub id : u64,
+    pub pos : ChunkPos,
+    pub output : JobOutput,
+}
+
+/// Returned when a job is submitted, used to match up results and to cancel the job.
+pub struct JobHandle {
+    pub id : u64,
+    cancelled : Arc<AtomicBool>,
+}
+
+impl JobHandle {
+    /// Workers skip cancelled jobs that haven't started yet.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Pool of worker threads that generate and mesh chunks off the render thread.
+/// Finished work is collected with `completed` and uploaded to the GPU by the caller.
+pub struct JobSystem {
+    sender : Option<Sender<Job>>,
+    results : Receiver<JobResult>,
+    workers : Vec<JoinHandle<()>>,
+    next_id : u64,
+}
+
+impl JobSystem {
+    pub fn new(worker_count : usize, generator : Arc<WorldGenerator>, textures : Arc<BlockTextures>) -> Self {
+        let (sender, jobs) = channel::<Job>();
+        let (result_sender, results) = channel();
+        let jobs = Arc::new(Mutex::new(jobs));
+
+        let workers = (0..worker_count.max(1))
+            .map(|index| {
+                let jobs = Arc::clone(&jobs);
+                let results = result_sender.clone();
+                let generator = Arc::clone(&generator);
+                let textures = Arc::clone(&textures);
+
+                std::thread::Builder::new()
+                    .name(format!("chunk worker {}", index))
+                    .spawn(move || worker_loop(jobs, results, generator, textures))
+                    .expect("failed to spawn chunk worker")
+            })
+            .collect();
+
+        JobSystem {
+            sender: Some(sender),
+            results,
+            workers,
+            next_id: 0,
+        }
+    }
+
+    pub fn submit(&mut self, pos : ChunkPos, kind : JobKind) -> JobHandle {
+        self.next_id += 1;
+        let cancelled = Arc::new(AtomicBool::new(false));
+
+        let job = Job {
+            id: self.next_id,
+            pos,
+            cancelled: Arc::clone(&cancelled),
+            kind,
+        };
+
+        if let Some(sender) = &self.sender {
+            // only fails if every worker has died, in which case the job is simply never done
+            let _ = sender.send(job);
+        }
+
+        JobHandle {
+            id: self.next_id,
+            cancelled,
+        }
+    }
+
+    /// Results finished since the last call, without blocking.
+    pub fn completed(&self) -> impl Iterator<Item = JobResult> + '_ {
+        self.results.try_iter()
+    }
+}
+
+impl Drop for JobSystem {
+    fn drop(&mut self) {
+        // closing the channel lets every worker fall out of its loop
+        self.sender = None;
+
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+fn worker_loop(jobs : Arc<Mutex<Receiver<Job>>>,
+               results : Sender<JobResult>,
+               generator : Arc<WorldGenerator>,
+               textures : Arc<BlockTextures>) {
+    loop {
+        // hold the lock only while waiting for the next job, not while working on it
+        let job = match jobs.lock() {
+            Ok(jobs) => jobs.recv(),
+            Err(_) => return,
+        };
+        let Ok(job) = job else {
+            return;
+        };
+
+        if job.cancelled.load(Ordering::Relaxed) {
+            continue;
+        }
+
+        let output = match job.kind {
+            JobKind::Generate => JobOutput::Chunk(generator.generate_chunk(job.pos)),
+            JobKind::Mesh { chunk, neighbours } => {
+                JobOutput::Mesh(mesh_chunk(&chunk, neighbours.each_ref().map(Option::as_ref), &textures))
+            }
+        };
+
+        let result = JobResult {
+            id: job.id,
+            pos: job.pos,
+            output,
+        };
+
+        if results.send(result).is_err() {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use crate::terrain::WorldType;
+
+    fn job_system() -> JobSystem {
+        JobSystem::new(
+            2,
+            Arc::new(WorldGenerator::new(WorldType::Terrain { seed: 5 })),
+            Arc::new(BlockTextures::untextured()),
+        )
+    }
+
+    #[test]
+    fn generated_chunks_come_back_with_their_position() {
+        let mut jobs = job_system();
+        let pos = ChunkPos::new(3, 0, -2);
+        let handle = jobs.submit(pos, JobKind::Generate);
+
+        let result = jobs.results.recv_timeout(Duration::from_secs(10)).unwrap();
+
+        assert_eq!(result.id, handle.id);
+        assert_eq!(result.pos, pos);
+        assert!(matches!(result.output, JobOutput::Chunk(_)));
+    }
+
+    #[test]
+    fn cancelled_jobs_produce_no_result() {
+        let mut jobs = job_system();
+
+        // queue a job that is already cancelled ahead of a live one
+        let cancelled = Job {
+            id: 1000,
+            pos: ChunkPos::new(0, 0, 0),
+            cancelled: Arc::new(AtomicBool::new(true)),
+            kind: JobKind::Generate,
+        };
+        jobs.sender.as_ref().unwrap().send(cancelled).unwrap();
+        let live = jobs.submit(ChunkPos::new(1, 0, 0), JobKind::Generate);
+
+        let result = jobs.results.recv_timeout(Duration::from_secs(10)).unwrap();
+
+        assert_eq!(result.id, live.id);
+        assert!(jobs.results.recv_timeout(Duration::from_millis(100)).is_err());
+    }
+
+    #[test]
+    fn mesh_jobs_return_vertices() {
+        let mut jobs = job_system();
+        let mut chunk = Chunk::default();
+        chunk.set(0, 0, 0, crate::block::BRICK);
+
+        jobs.submit(ChunkPos::new(0, 0, 0), JobKind::Mesh { chunk, neighbours: Default::default() });
+
+        match jobs.results.recv_timeout(Duration::from_secs(10)).unwrap().output {
+            JobOutput::Mesh(mesh) => assert_eq!(mesh.quad_count, 6),
+            JobOutput::Chunk(_) => panic!("expected a mesh"),
+        }
+    }
+}
@@ -1,10 +1,11 @@
-use gl::types::GLenum;
-use glutin::event::{ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent};
-use glutin::event_loop::ControlFlow;
+use std::sync::OnceLock;
+use gl::types::{GLenum, GLint};
+use serde::Deserialize;
 
 // code found: https://rust-tutorials.github.io/learn-opengl/basics/003-rectangle.html
 /// The polygon display modes you can set.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum PolygonMode {
     /// Just show the points.
     Point = gl::POINT as isize,
@@ -14,8 +15,36 @@ pub enum PolygonMode {
     Fill = gl::FILL as isize,
 }
 
+impl PolygonMode {
+    /// The mode after this one, for cycling through them at runtime.
+    pub fn next(self) -> PolygonMode {
+        match self {
+            PolygonMode::Fill => PolygonMode::Line,
+            PolygonMode::Line => PolygonMode::Point,
+            PolygonMode::Point => PolygonMode::Fill,
+        }
+    }
+}
+
 /// Sets the font and back polygon mode to the mode given.
 pub fn polygon_mode(mode: PolygonMode) {
     unsafe { gl::PolygonMode(gl::FRONT_AND_BACK, mode as GLenum) };
 }
 
+
+/// Whether the current context is at least OpenGL `major`.`minor`. Entry points can't be trusted
+/// for this, some drivers hand out a pointer for any name. The version is read once, on first use.
+pub fn supports_gl(major : GLint, minor : GLint) -> bool {
+    static VERSION : OnceLock<(GLint, GLint)> = OnceLock::new();
+
+    let version = *VERSION.get_or_init(|| {
+        let (mut major, mut minor) = (0, 0);
+        unsafe {
+            gl::GetIntegerv(gl::MAJOR_VERSION, &mut major);
+            gl::GetIntegerv(gl::MINOR_VERSION, &mut minor);
+        }
+        (major, minor)
+    });
+
+    version >= (major, minor)
+}
@@ -1,16 +1,43 @@
 mod shader;
 mod camera;
 mod game_window;
-mod cube;
+mod input;
+mod bindings;
+mod atlas;
+mod block;
+mod chunk;
+mod chunk_manager;
+mod jobs;
+mod mesher;
+mod terrain;
 mod texture;
-mod game_specs;
+mod settings;
+mod timing;
 mod world;
+mod raycast;
 mod renderer;
+mod save;
 mod game;
+mod error;
+mod vertex;
+mod storage_buffer;
+mod particles;
 
+use crate::error::Error;
 use crate::game::Game;
+use crate::settings::Settings;
 
 fn main() {
-    let game = Game::new();
-    game.run();
+    if let Err(error) = run() {
+        println!("{}", error);
+        std::process::exit(1);
+    }
+}
+
+fn run() -> Result<(), Error> {
+    // settings come from config/settings.toml (or --config) with --set overrides on top
+    let settings = Settings::from_args(std::env::args().skip(1))?;
+
+    let game = Game::new(settings);
+    game.run()
 }
@@ -0,0 +1,363 @@
+use crate::block::{is_opaque, is_solid, BlockId, BlockTextures, Face, AIR};
+use crate::atlas::AtlasRegion;
+use crate::chunk::{Chunk, CHUNK_SIZE};
+use crate::vertex::{VertexAttribute, VertexLayout};
+
+/// The vertices `mesh_chunk` emits, as the block shader reads them.
+pub const CHUNK_VERTEX_LAYOUT : VertexLayout = VertexLayout {
+    attributes: &[
+        VertexAttribute::float("position", 3),
+        // counted in blocks, wrapped into the region by the shader
+        VertexAttribute::float("texture", 2),
+        VertexAttribute::float("atlas_region", 4),
+    ],
+};
+
+/// CPU side vertex data for one chunk, positions are relative to the chunk origin.
+pub struct ChunkMesh {
+    pub vertices : Vec<f32>,
+    pub quad_count : usize,
+}
+
+impl ChunkMesh {
+    pub fn is_empty(&self) -> bool {
+        self.quad_count == 0
+    }
+}
+
+/// Builds the visible surface of a chunk.
+///
+/// Only faces of solid blocks that aren't hidden behind an opaque block are emitted, and
+/// neighbouring faces in the same plane with the same block are merged into a single quad
+/// (greedy meshing). `neighbours` are the adjacent chunks in `NEIGHBOUR_OFFSETS` order,
+/// missing ones count as air.
+pub fn mesh_chunk(chunk : &Chunk, neighbours : [Option<&Chunk>; 6], textures : &BlockTextures) -> ChunkMesh {
+    let mut mesh = ChunkMesh {
+        vertices: Vec::new(),
+        quad_count: 0,
+    };
+
+    let size = CHUNK_SIZE as i32;
+    let mut mask = [AIR; CHUNK_SIZE * CHUNK_SIZE];
+
+    // sweep a plane along each axis, d is the face normal axis and u, v span the plane
+    for d in 0..3 {
+        let u = (d + 1) % 3;
+        let v = (d + 2) % 3;
+
+        for positive in [false, true] {
+            let step = if positive { 1 } else { -1 };
+
+            for slice in 0..size {
+                // mark every block face in this slice that can be seen
+                for j in 0..size {
+                    for i in 0..size {
+                        let mut pos = [0; 3];
+                        pos[d] = slice;
+                        pos[u] = i;
+                        pos[v] = j;
+
+                        let block = block_at(chunk, &neighbours, pos);
+                        pos[d] += step;
+                        let facing = block_at(chunk, &neighbours, pos);
+
+                        // faces between two blocks of the same transparent type are hidden too
+                        let visible = is_solid(block) && !is_opaque(facing) && facing != block;
+                        mask[(j * size + i) as usize] = if visible {
+                            block
+                        } else {
+                            AIR
+                        };
+                    }
+                }
+
+                // merge the marked faces into as few rectangles as possible
+                for j in 0..size {
+                    let mut i = 0;
+                    while i < size {
+                        let block = mask[(j * size + i) as usize];
+                        if block == AIR {
+                            i += 1;
+                            continue;
+                        }
+
+                        let mut width = 1;
+                        while i + width < size && mask[(j * size + i + width) as usize] == block {
+                            width += 1;
+                        }
+
+                        let mut height = 1;
+                        'grow: while j + height < size {
+                            for k in 0..width {
+                                if mask[((j + height) * size + i + k) as usize] != block {
+                                    break 'grow;
+                                }
+                            }
+                            height += 1;
+                        }
+
+                        // the face sits on the far side of the block for positive normals
+                        let mut origin = [0; 3];
+                        origin[d] = if positive { slice + 1 } else { slice };
+                        origin[u] = i;
+                        origin[v] = j;
+
+                        let face = match (d, positive) {
+                            (1, true) => Face::Top,
+                            (1, false) => Face::Bottom,
+                            _ => Face::Side,
+                        };
+                        let region = textures.region(block, face);
+
+                        emit_quad(&mut mesh, region, origin, d, width, height, positive);
+
+                        for y in 0..height {
+                            for x in 0..width {
+                                mask[((j + y) * size + i + x) as usize] = AIR;
+                            }
+                        }
+
+                        i += width;
+                    }
+                }
+            }
+        }
+    }
+
+    mesh
+}
+
+// look up a block that may be one step outside the chunk along a single axis
+fn block_at(chunk : &Chunk, neighbours : &[Option<&Chunk>; 6], pos : [i32; 3]) -> BlockId {
+    let size = CHUNK_SIZE as i32;
+
+    for axis in 0..3 {
+        if pos[axis] < 0 || pos[axis] >= size {
+            let neighbour = neighbours[axis * 2 + (pos[axis] >= size) as usize];
+            let mut inside = pos;
+            inside[axis] = pos[axis].rem_euclid(size);
+
+            return match neighbour {
+                Some(neighbour) => neighbour.get(inside[0] as usize, inside[1] as usize, inside[2] as usize),
+                None => AIR,
+            };
+        }
+    }
+
+    chunk.get(pos[0] as usize, pos[1] as usize, pos[2] as usize)
+}
+
+fn emit_quad(mesh : &mut ChunkMesh,
+             region : AtlasRegion,
+             origin : [i32; 3],
+             d : usize,
+             width : i32,
+             height : i32,
+             positive : bool) {
+    let u = (d + 1) % 3;
+    let v = (d + 2) % 3;
+
+    let mut du = [0; 3];
+    du[u] = width;
+    let mut dv = [0; 3];
+    dv[v] = height;
+
+    let corner = |a : [i32; 3], b : [i32; 3]| {
+        [
+            (origin[0] + a[0] + b[0]) as f32,
+            (origin[1] + a[1] + b[1]) as f32,
+            (origin[2] + a[2] + b[2]) as f32,
+        ]
+    };
+    let corners = [
+        corner([0; 3], [0; 3]),
+        corner(du, [0; 3]),
+        corner(du, dv),
+        corner([0; 3], dv),
+    ];
+
+    // u x v points along +d, so this order is counter-clockwise when seen from the positive side
+    let order: [usize; 6] = if positive {
+        [0, 1, 2, 2, 3, 0]
+    } else {
+        [0, 3, 2, 2, 1, 0]
+    };
+
+    for index in order {
+        let [x, y, z] = corners[index];
+        // texture coordinates are in blocks, the shader wraps them into the block's atlas region
+        // so the texture repeats once per block across a merged quad
+        let (tex_u, tex_v) = match d {
+            0 => (z, y),
+            1 => (x, z),
+            _ => (x, y),
+        };
+
+        mesh.vertices.extend_from_slice(&[
+            x, y, z,
+            tex_u, tex_v,
+            region.u, region.v, region.width, region.height,
+        ]);
+    }
+
+    mesh.quad_count += 1;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::{BRICK, SMILEY, WOOD};
+
+    // floats per vertex: position (x, y, z) + texture coordinates (u, v) + atlas region (u, v, width, height)
+    const VERTEX_SIZE : usize = 9;
+
+    const NO_NEIGHBOURS : [Option<&Chunk>; 6] = [None; 6];
+
+    fn mesh(chunk : &Chunk, neighbours : [Option<&Chunk>; 6]) -> ChunkMesh {
+        mesh_chunk(chunk, neighbours, &BlockTextures::untextured())
+    }
+
+    #[test]
+    fn empty_chunk_has_no_faces() {
+        let mesh = mesh(&Chunk::default(), NO_NEIGHBOURS);
+
+        assert!(mesh.is_empty());
+        assert!(mesh.vertices.is_empty());
+    }
+
+    #[test]
+    fn single_block_has_six_faces() {
+        let mut chunk = Chunk::default();
+        chunk.set(4, 5, 6, BRICK);
+
+        let mesh = mesh(&chunk, NO_NEIGHBOURS);
+
+        assert_eq!(mesh.quad_count, 6);
+        assert_eq!(mesh.vertices.len(), 6 * 6 * VERTEX_SIZE);
+        assert_eq!(CHUNK_VERTEX_LAYOUT.stride(), VERTEX_SIZE * std::mem::size_of::<f32>());
+    }
+
+    #[test]
+    fn matching_neighbours_merge_into_one_box() {
+        let mut chunk = Chunk::default();
+        chunk.set(0, 0, 0, BRICK);
+        chunk.set(1, 0, 0, BRICK);
+
+        assert_eq!(mesh(&chunk, NO_NEIGHBOURS).quad_count, 6);
+    }
+
+    #[test]
+    fn different_blocks_cull_but_do_not_merge() {
+        let mut chunk = Chunk::default();
+        chunk.set(0, 0, 0, BRICK);
+        chunk.set(1, 0, 0, WOOD);
+
+        // shared face is hidden, the four long sides stay split by block type
+        assert_eq!(mesh(&chunk, NO_NEIGHBOURS).quad_count, 10);
+    }
+
+    #[test]
+    fn transparent_blocks_do_not_hide_faces() {
+        let mut chunk = Chunk::default();
+        chunk.set(0, 0, 0, BRICK);
+        chunk.set(1, 0, 0, SMILEY);
+
+        // the brick face behind the smiley is still drawn, the smiley face against the brick isn't
+        assert_eq!(mesh(&chunk, NO_NEIGHBOURS).quad_count, 11);
+    }
+
+    #[test]
+    fn matching_transparent_blocks_merge() {
+        let mut chunk = Chunk::default();
+        chunk.set(0, 0, 0, SMILEY);
+        chunk.set(1, 0, 0, SMILEY);
+
+        assert_eq!(mesh(&chunk, NO_NEIGHBOURS).quad_count, 6);
+    }
+
+    #[test]
+    fn solid_layer_is_six_quads() {
+        let mut chunk = Chunk::default();
+        for x in 0..CHUNK_SIZE {
+            for z in 0..CHUNK_SIZE {
+                chunk.set(x, 3, z, WOOD);
+            }
+        }
+
+        assert_eq!(mesh(&chunk, NO_NEIGHBOURS).quad_count, 6);
+    }
+
+    #[test]
+    fn full_chunk_is_six_quads() {
+        let mut chunk = Chunk::default();
+        for x in 0..CHUNK_SIZE {
+            for y in 0..CHUNK_SIZE {
+                for z in 0..CHUNK_SIZE {
+                    chunk.set(x, y, z, BRICK);
+                }
+            }
+        }
+
+        assert_eq!(mesh(&chunk, NO_NEIGHBOURS).quad_count, 6);
+    }
+
+    #[test]
+    fn checkerboard_cannot_merge() {
+        let mut chunk = Chunk::default();
+        let mut solid = 0;
+        for x in 0..CHUNK_SIZE {
+            for y in 0..CHUNK_SIZE {
+                for z in 0..CHUNK_SIZE {
+                    if (x + y + z) % 2 == 0 {
+                        chunk.set(x, y, z, BRICK);
+                        solid += 1;
+                    }
+                }
+            }
+        }
+
+        assert_eq!(mesh(&chunk, NO_NEIGHBOURS).quad_count, solid * 6);
+    }
+
+    #[test]
+    fn faces_against_neighbour_chunks_are_culled() {
+        let mut chunk = Chunk::default();
+        chunk.set(CHUNK_SIZE - 1, 0, 0, BRICK);
+
+        let mut neighbour = Chunk::default();
+        neighbour.set(0, 0, 0, WOOD);
+
+        let mut neighbours = NO_NEIGHBOURS;
+        neighbours[1] = Some(&neighbour);
+
+        assert_eq!(mesh(&chunk, neighbours).quad_count, 5);
+    }
+
+    #[test]
+    fn quads_face_outwards() {
+        let mut chunk = Chunk::default();
+        chunk.set(0, 0, 0, BRICK);
+
+        let mesh = mesh(&chunk, NO_NEIGHBOURS);
+
+        // every triangle's normal should point away from the block centre
+        for triangle in mesh.vertices.chunks(3 * VERTEX_SIZE) {
+            let p = |n : usize| {
+                let start = n * VERTEX_SIZE;
+                [triangle[start], triangle[start + 1], triangle[start + 2]]
+            };
+            let (a, b, c) = (p(0), p(1), p(2));
+            let e1 = [b[0] - a[0], b[1] - a[1], b[2] - a[2]];
+            let e2 = [c[0] - a[0], c[1] - a[1], c[2] - a[2]];
+            let normal = [
+                e1[1] * e2[2] - e1[2] * e2[1],
+                e1[2] * e2[0] - e1[0] * e2[2],
+                e1[0] * e2[1] - e1[1] * e2[0],
+            ];
+            let to_face = [a[0] - 0.5, a[1] - 0.5, a[2] - 0.5];
+            let dot = normal[0] * to_face[0] + normal[1] * to_face[1] + normal[2] * to_face[2];
+
+            assert!(dot > 0.0);
+        }
+    }
+}
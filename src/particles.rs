@@ -0,0 +1,182 @@
+use cgmath::{vec3, Matrix4, SquareMatrix};
+use gl::types::{GLsizei, GLuint};
+use crate::error::Error;
+use crate::settings::AssetSettings;
+use crate::shader::Shader;
+use crate::storage_buffer::StorageBuffer;
+use crate::vertex::{VertexAttribute, VertexLayout};
+
+const MAX_PARTICLES : usize = 1024;
+const PARTICLES_PER_BURST : usize = 32;
+// has to match the local size the compute shader is built with
+const WORK_GROUP_SIZE : usize = 64;
+// blocks per second squared
+const GRAVITY : f32 = 9.8;
+const LIFETIME : f32 = 1.2;
+
+// the storage buffer doubles as the vertex buffer particles are drawn from
+const PARTICLE_VERTEX_LAYOUT : VertexLayout = VertexLayout {
+    attributes: &[
+        VertexAttribute::float("position", 4),
+        VertexAttribute::float("velocity", 4),
+    ],
+};
+
+// std430 layout of the compute shader's Particle struct
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct Particle {
+    // w is the time left to live in seconds
+    position : [f32; 4],
+    velocity : [f32; 4],
+}
+
+/// Dust thrown up by broken blocks. A compute shader moves the particles, so they never
+/// come back to the CPU, and the same buffer is drawn as points.
+pub struct Particles {
+    update_shader : Shader,
+    draw_shader : Shader,
+    buffer : StorageBuffer,
+    vao : GLuint,
+    // where the next burst goes, the oldest particles are overwritten first
+    next : usize,
+    // until every particle is dead, nothing needs simulating or drawing after that
+    time_left : f32,
+}
+
+impl Particles {
+    /// Fails with `Error::Unsupported` below GL 4.3, which has no compute shaders.
+    pub unsafe fn new(assets : &AssetSettings) -> Result<Self, Error> {
+        let buffer = StorageBuffer::new(&[Particle::default(); MAX_PARTICLES])?;
+
+        let update_shader = Shader::builder()
+            .compute(&assets.particle_compute_shader)
+            .define("WORK_GROUP_SIZE", &WORK_GROUP_SIZE.to_string())
+            .build()?;
+        let draw_shader = Shader::builder()
+            .vertex(&assets.particle_vertex_shader)
+            .fragment(&assets.line_fragment_shader)
+            .layout(&PARTICLE_VERTEX_LAYOUT)
+            .build()?;
+
+        let mut vao = 0;
+        gl::GenVertexArrays(1, &mut vao);
+        gl::BindVertexArray(vao);
+        gl::BindBuffer(gl::ARRAY_BUFFER, buffer.id);
+        PARTICLE_VERTEX_LAYOUT.apply();
+        gl::BindVertexArray(0);
+        gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+
+        Ok(Particles {
+            update_shader,
+            draw_shader,
+            buffer,
+            vao,
+            next: 0,
+            time_left: 0.0,
+        })
+    }
+
+    /// Throws a burst of particles out of the block at `block`.
+    pub unsafe fn burst(&mut self, block : [i32; 3]) {
+        let centre = block.map(|coordinate| coordinate as f32 + 0.5);
+        self.buffer.write(self.next, &burst_particles(centre));
+
+        self.next = (self.next + PARTICLES_PER_BURST) % MAX_PARTICLES;
+        self.time_left = LIFETIME;
+    }
+
+    pub unsafe fn update(&mut self, delta_time : f32) {
+        if self.time_left <= 0.0 {
+            return;
+        }
+        self.time_left -= delta_time;
+
+        gl::UseProgram(self.update_shader.id);
+        self.update_shader.set_uniform("delta_time", &delta_time);
+        self.update_shader.set_uniform("gravity", &GRAVITY);
+        self.buffer.bind(0);
+
+        let groups = MAX_PARTICLES.div_ceil(WORK_GROUP_SIZE) as u32;
+        // the results are read back as vertex attributes, and the next burst's upload has to wait for them
+        self.update_shader.dispatch([groups, 1, 1], gl::VERTEX_ATTRIB_ARRAY_BARRIER_BIT | gl::BUFFER_UPDATE_BARRIER_BIT);
+    }
+
+    /// Leaves the draw program in use.
+    pub unsafe fn render(&self, projection : Matrix4<f32>, view : Matrix4<f32>) {
+        if self.time_left <= 0.0 {
+            return;
+        }
+
+        gl::UseProgram(self.draw_shader.id);
+        self.draw_shader.set_uniform("projection", &projection);
+        self.draw_shader.set_uniform("view", &view);
+        self.draw_shader.set_uniform("model", &Matrix4::<f32>::identity());
+        self.draw_shader.set_uniform("colour", &vec3(0.45, 0.4, 0.35));
+
+        gl::PointSize(3.0);
+        gl::BindVertexArray(self.vao);
+        gl::DrawArrays(gl::POINTS, 0, MAX_PARTICLES as GLsizei);
+        gl::BindVertexArray(0);
+    }
+
+    // named for reload messages
+    pub fn shaders(&mut self) -> [(&'static str, &mut Shader); 2] {
+        [("particle update", &mut self.update_shader), ("particle", &mut self.draw_shader)]
+    }
+}
+
+impl Drop for Particles {
+    fn drop(&mut self) {
+        unsafe { gl::DeleteVertexArrays(1, &self.vao); }
+    }
+}
+
+// spread evenly around the block and thrown upwards, so no random numbers are needed
+fn burst_particles(centre : [f32; 3]) -> [Particle; PARTICLES_PER_BURST] {
+    // the golden angle keeps consecutive particles from lining up
+    const GOLDEN_ANGLE : f32 = 2.399_963;
+
+    let mut particles = [Particle::default(); PARTICLES_PER_BURST];
+    for (index, particle) in particles.iter_mut().enumerate() {
+        let angle = index as f32 * GOLDEN_ANGLE;
+        let spread = 0.5 + (index % 4) as f32 * 0.4;
+        let direction = vec3(angle.cos(), 0.0, angle.sin());
+
+        particle.position = [
+            centre[0] + direction.x * 0.3,
+            centre[1],
+            centre[2] + direction.z * 0.3,
+            LIFETIME * (0.6 + (index % 5) as f32 * 0.1),
+        ];
+        particle.velocity = [direction.x * spread, 2.0 + (index % 3) as f32 * 0.8, direction.z * spread, 0.0];
+    }
+
+    particles
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::mem;
+
+    #[test]
+    fn particles_match_the_vertex_layout() {
+        assert_eq!(PARTICLE_VERTEX_LAYOUT.stride(), mem::size_of::<Particle>());
+        assert_eq!(MAX_PARTICLES % PARTICLES_PER_BURST, 0, "bursts would run past the end of the buffer");
+    }
+
+    #[test]
+    fn bursts_start_alive_and_leave_the_block() {
+        let particles = burst_particles([0.5, 10.5, 0.5]);
+
+        for particle in &particles {
+            assert!(particle.position[3] > 0.0 && particle.position[3] <= LIFETIME);
+            assert!(particle.velocity[1] > 0.0);
+        }
+        // nothing lands on the same spot
+        for (index, particle) in particles.iter().enumerate() {
+            assert!(particles[..index].iter().all(|other| other.position != particle.position));
+        }
+    }
+}
@@ -0,0 +1,172 @@
+use cgmath::{InnerSpace, Point3, Vector3};
+
+/// The first solid block a ray runs into.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RaycastHit {
+    pub block : [i32; 3],
+    /// Outward normal of the face the ray entered through, zero if the ray started inside the block.
+    pub normal : [i32; 3],
+    pub distance : f32,
+}
+
+impl RaycastHit {
+    /// The empty block in front of the hit face, where a new block would be placed.
+    pub fn adjacent(&self) -> [i32; 3] {
+        [
+            self.block[0] + self.normal[0],
+            self.block[1] + self.normal[1],
+            self.block[2] + self.normal[2],
+        ]
+    }
+}
+
+/// Walks the voxel grid along a ray (Amanatides & Woo), visiting every block the ray passes
+/// through in order, and returns the first one `is_solid` accepts within `max_distance`.
+/// Block (x, y, z) fills the unit cube from (x, y, z) to (x + 1, y + 1, z + 1).
+pub fn raycast<F : Fn(i32, i32, i32) -> bool>(origin : Point3<f32>,
+                                              direction : Vector3<f32>,
+                                              max_distance : f32,
+                                              is_solid : F) -> Option<RaycastHit> {
+    if direction.magnitude2() == 0.0 {
+        return None;
+    }
+    let direction = direction.normalize();
+
+    let origin = [origin.x, origin.y, origin.z];
+    let direction = [direction.x, direction.y, direction.z];
+
+    let mut block = origin.map(|coordinate| coordinate.floor() as i32);
+    let mut step = [0; 3];
+    // distance along the ray to the next block boundary on each axis
+    let mut t_max = [f32::INFINITY; 3];
+    // distance along the ray between boundaries on each axis
+    let mut t_delta = [f32::INFINITY; 3];
+
+    for axis in 0..3 {
+        if direction[axis] > 0.0 {
+            step[axis] = 1;
+            t_delta[axis] = 1.0 / direction[axis];
+            t_max[axis] = (block[axis] as f32 + 1.0 - origin[axis]) * t_delta[axis];
+        } else if direction[axis] < 0.0 {
+            step[axis] = -1;
+            t_delta[axis] = -1.0 / direction[axis];
+            t_max[axis] = (origin[axis] - block[axis] as f32) * t_delta[axis];
+        }
+    }
+
+    let mut normal = [0; 3];
+    let mut distance = 0.0;
+
+    loop {
+        if is_solid(block[0], block[1], block[2]) {
+            return Some(RaycastHit {
+                block,
+                normal,
+                distance,
+            });
+        }
+
+        // step into the neighbouring block across whichever boundary is closest
+        let axis = if t_max[0] < t_max[1] {
+            if t_max[0] < t_max[2] { 0 } else { 2 }
+        } else if t_max[1] < t_max[2] {
+            1
+        } else {
+            2
+        };
+
+        distance = t_max[axis];
+        if distance > max_distance {
+            return None;
+        }
+
+        block[axis] += step[axis];
+        t_max[axis] += t_delta[axis];
+        normal = [0; 3];
+        normal[axis] = -step[axis];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+    use cgmath::vec3;
+
+    fn grid(blocks : &[[i32; 3]]) -> impl Fn(i32, i32, i32) -> bool {
+        let blocks: HashSet<[i32; 3]> = blocks.iter().copied().collect();
+        move |x, y, z| blocks.contains(&[x, y, z])
+    }
+
+    #[test]
+    fn hits_block_straight_ahead() {
+        let hit = raycast(Point3::new(0.5, 0.5, 0.5), vec3(0.0, 0.0, -1.0), 10.0, grid(&[[0, 0, -3]])).unwrap();
+
+        assert_eq!(hit.block, [0, 0, -3]);
+        assert_eq!(hit.normal, [0, 0, 1]);
+        assert!((hit.distance - 2.5).abs() < 1e-5);
+        assert_eq!(hit.adjacent(), [0, 0, -2]);
+    }
+
+    #[test]
+    fn reports_the_face_entered() {
+        let solid = grid(&[[5, 0, 0]]);
+
+        let from_below = raycast(Point3::new(5.5, -3.0, 0.5), vec3(0.0, 1.0, 0.0), 10.0, &solid).unwrap();
+        assert_eq!(from_below.normal, [0, -1, 0]);
+
+        let from_the_left = raycast(Point3::new(0.5, 0.5, 0.5), vec3(1.0, 0.0, 0.0), 10.0, &solid).unwrap();
+        assert_eq!(from_the_left.normal, [-1, 0, 0]);
+    }
+
+    #[test]
+    fn returns_the_nearest_of_several_blocks() {
+        let solid = grid(&[[4, 0, 0], [2, 0, 0], [7, 0, 0]]);
+
+        let hit = raycast(Point3::new(0.5, 0.5, 0.5), vec3(1.0, 0.0, 0.0), 10.0, solid).unwrap();
+
+        assert_eq!(hit.block, [2, 0, 0]);
+    }
+
+    #[test]
+    fn misses_beyond_max_distance() {
+        let solid = grid(&[[0, 0, -9]]);
+
+        assert!(raycast(Point3::new(0.5, 0.5, 0.5), vec3(0.0, 0.0, -1.0), 5.0, solid).is_none());
+    }
+
+    #[test]
+    fn diagonal_ray_does_not_skip_blocks() {
+        // the ray crosses x and y boundaries in turn and has to visit every block in between
+        let solid = grid(&[[3, 2, 0]]);
+
+        let hit = raycast(Point3::new(0.2, 0.5, 0.5), vec3(1.0, 0.7, 0.0), 20.0, solid).unwrap();
+
+        assert_eq!(hit.block, [3, 2, 0]);
+        assert!(hit.normal == [-1, 0, 0] || hit.normal == [0, -1, 0]);
+    }
+
+    #[test]
+    fn negative_coordinates_round_down() {
+        let solid = grid(&[[-2, -1, -1]]);
+
+        let hit = raycast(Point3::new(-0.5, -0.5, -0.5), vec3(-1.0, 0.0, 0.0), 10.0, solid).unwrap();
+
+        assert_eq!(hit.block, [-2, -1, -1]);
+        assert_eq!(hit.normal, [1, 0, 0]);
+    }
+
+    #[test]
+    fn starting_inside_a_block_hits_it_immediately() {
+        let hit = raycast(Point3::new(1.5, 1.5, 1.5), vec3(0.0, 1.0, 0.0), 10.0, grid(&[[1, 1, 1]])).unwrap();
+
+        assert_eq!(hit.block, [1, 1, 1]);
+        assert_eq!(hit.normal, [0, 0, 0]);
+        assert_eq!(hit.distance, 0.0);
+    }
+
+    #[test]
+    fn zero_direction_hits_nothing() {
+        assert!(raycast(Point3::new(0.5, 0.5, 0.5), vec3(0.0, 0.0, 0.0), 10.0, grid(&[[0, 0, 0]])).is_none());
+    }
+}
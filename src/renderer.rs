@@ -1,126 +1,415 @@
-use std::ffi::CString;
+use std::collections::HashMap;
 use std::mem;
-use cgmath::Matrix4;
-use gl::types::{GLenum, GLfloat, GLsizei, GLuint};
+use cgmath::{vec3, Matrix4};
+use gl::types::{GLenum, GLsizei, GLuint};
 use glutin_opengl_demo::{polygon_mode, PolygonMode};
-use crate::game_specs::POLYGON_MODE;
+use crate::atlas::Atlas;
+use crate::chunk::{ChunkPos, CHUNK_SIZE};
+use crate::chunk_manager::ChunkMeshes;
+use crate::error::Error;
+use crate::mesher::{ChunkMesh, CHUNK_VERTEX_LAYOUT};
+use crate::particles::Particles;
+use crate::settings::Settings;
 use crate::shader::Shader;
 use crate::texture::Texture;
-use crate::world::World;
+use crate::vertex::{VertexAttribute, VertexLayout};
+
+// handle to the GPU buffers holding one chunk's vertices
+pub struct Mesh {
+    vao : GLuint,
+    vbo : GLuint,
+    vertex_count : GLsizei,
+}
+
+/// What the block shader shows instead of textures, for checking meshes and the camera.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DebugView {
+    Textured = 0,
+    Normals = 1,
+    TextureCoordinates = 2,
+    Depth = 3,
+}
+
+impl DebugView {
+    pub fn next(self) -> DebugView {
+        match self {
+            DebugView::Textured => DebugView::Normals,
+            DebugView::Normals => DebugView::TextureCoordinates,
+            DebugView::TextureCoordinates => DebugView::Depth,
+            DebugView::Depth => DebugView::Textured,
+        }
+    }
+}
+
+// the outline is drawn slightly larger than a block so it doesn't z-fight with the block's faces
+const OUTLINE_MARGIN : f32 = 0.002;
+
+// the outline is drawn as lines between the corners of a unit cube
+const OUTLINE_VERTEX_LAYOUT : VertexLayout = VertexLayout {
+    attributes: &[VertexAttribute::float("position", 3)],
+};
 
 pub struct Renderer {
     shader_program : Shader,
-    vao : GLuint,
-    texture1 : Texture
+    chunk_meshes : HashMap<ChunkPos, Mesh>,
+    atlas : Texture,
+    polygon_mode : PolygonMode,
+    clear_colour : [f32; 3],
+    // None when the block shader was built without debug views
+    debug_view : Option<DebugView>,
+    show_chunk_borders : bool,
+    // clip planes, for the depth view
+    near : f32,
+    far : f32,
+    // wireframe box drawn around the targeted block, and around chunks when their borders are shown
+    line_shader : Shader,
+    outline : Mesh,
+    normal_shader : Shader,
+    show_normals : bool,
+    // None when the driver is older than GL 4.3
+    particles : Option<Particles>,
 }
 
 impl Renderer {
 
-    pub fn new() -> Self {
+    pub fn new(atlas : &Atlas, settings : &Settings, near : f32, far : f32) -> Result<Self, Error> {
+        let assets = &settings.assets;
+        let mut block_shader = Shader::builder()
+            .vertex(&assets.block_vertex_shader)
+            .fragment(&assets.block_fragment_shader)
+            .layout(&CHUNK_VERTEX_LAYOUT);
+        if settings.graphics.debug_views {
+            block_shader = block_shader.define("DEBUG_VIEWS", "1");
+        }
+        let shader_program = block_shader.build()?;
+        let atlas = unsafe { Texture::from_image(&atlas.image)? };
 
-        //TODO shouldn't be hard coded
-        let shader_program = Shader::new("shaders/shader.vs", "shaders/shader.fs");
-        let texture1 = unsafe { Texture::new("resources/textures/wall.jpeg") };
+        let line_shader = Shader::builder()
+            .vertex(&assets.line_vertex_shader)
+            .fragment(&assets.line_fragment_shader)
+            .layout(&OUTLINE_VERTEX_LAYOUT)
+            .build()?;
+        // the geometry stage turns each triangle into a line along its normal
+        let normal_shader = Shader::builder()
+            .vertex(&assets.normal_vertex_shader)
+            .geometry(&assets.normal_geometry_shader)
+            .fragment(&assets.line_fragment_shader)
+            .layout(&CHUNK_VERTEX_LAYOUT)
+            .build()?;
+        let outline = unsafe { create_outline_mesh() };
+        let particles = match unsafe { Particles::new(assets) } {
+            Ok(particles) => Some(particles),
+            // everything else still works on older drivers
+            Err(error @ Error::Unsupported { .. }) => {
+                println!("Block particles are turned off, {}", error);
+                None
+            }
+            Err(error) => return Err(error),
+        };
 
-        Renderer {
+        Ok(Renderer {
             shader_program,
-            vao: 0,
-            texture1,
-        }
+            chunk_meshes: HashMap::new(),
+            atlas,
+            polygon_mode: settings.graphics.polygon_mode,
+            clear_colour: settings.graphics.clear_colour,
+            debug_view: settings.graphics.debug_views.then_some(DebugView::Textured),
+            show_chunk_borders: false,
+            near,
+            far,
+            line_shader,
+            outline,
+            normal_shader,
+            show_normals: false,
+            particles,
+        })
     }
 
-    pub fn init_renderer(&mut self, world : World) {
+    pub fn init_renderer(&mut self) {
         unsafe {
-            gl::UseProgram(self.shader_program.id);
             gl::Enable(gl::DEPTH_TEST);
+            self.apply_block_uniforms();
+        }
 
-            // Generate and bind vertex array object (VAO)
-            gl::GenVertexArrays(1, &mut self.vao);
-            gl::BindVertexArray(self.vao);
+        // "settings"
+        let [red, green, blue] = self.clear_colour;
+        unsafe { gl::ClearColor(red, green, blue, 1.0); }
+        polygon_mode(self.polygon_mode);
+    }
 
-            for cube in world.objects {
-                // Generate and bind vertex buffer object (VBO)
-                define_buffer(
-                    gl::ARRAY_BUFFER,
-                    &cube.vertices,
-                    gl::STATIC_DRAW
-                );
+    // uniforms that only change on request, these reset whenever the program is rebuilt
+    unsafe fn apply_block_uniforms(&self) {
+        gl::UseProgram(self.shader_program.id);
+
+        //assign shader sampler to texture unit
+        self.shader_program.set_uniform("atlas", &0);
+
+        if let Some(debug_view) = self.debug_view {
+            self.shader_program.set_uniform("debug_view", &(debug_view as i32));
+            self.shader_program.set_uniform("near", &self.near);
+            self.shader_program.set_uniform("far", &self.far);
+        }
+    }
+
+    /// Rebuilds any shader whose files were edited since the last call.
+    /// A shader that no longer compiles keeps drawing with its previous program.
+    pub fn reload_shaders(&mut self) {
+        let reload = |name : &str, shader : &mut Shader| match shader.reload_if_changed() {
+            Ok(reloaded) => {
+                if reloaded {
+                    println!("Reloaded the {} shader", name);
+                }
+                reloaded
+            }
+            Err(error) => {
+                println!("Keeping the previous {} shader, {}", name, error);
+                false
             }
+        };
 
-            // define attribute pointers
-            //TODO hard-coding stride size for now
-            let stride = (5 * mem::size_of::<GLfloat>()) as GLsizei;
-            self.define_attrib_pointers(stride);
+        // the line shader sets everything it needs each frame, the block shader starts over with defaults
+        if reload("block", &mut self.shader_program) {
+            unsafe { self.apply_block_uniforms(); }
+        }
+        reload("line", &mut self.line_shader);
+        reload("normal", &mut self.normal_shader);
+        if let Some(particles) = &mut self.particles {
+            for (name, shader) in particles.shaders() {
+                reload(name, shader);
+            }
+        }
+    }
 
-            //assign shader sampler to texture unit
-            self.shader_program.set_int(&CString::new("texture1").unwrap(), 0);
+    /// Matches the viewport to the window's drawable size, in physical pixels.
+    pub fn resize(&mut self, width : u32, height : u32) {
+        // minimising reports a zero size, keep the old viewport until the window comes back
+        if width == 0 || height == 0 {
+            return;
         }
 
-        // "settings"
-        unsafe { gl::ClearColor(0.7, 0.7, 0.8, 1.0); }
-        polygon_mode(POLYGON_MODE);
+        unsafe { gl::Viewport(0, 0, width as GLsizei, height as GLsizei); }
+    }
+
+    /// Switches to the next of filled, wireframe and point polygons.
+    pub fn cycle_polygon_mode(&mut self) -> PolygonMode {
+        self.polygon_mode = self.polygon_mode.next();
+        polygon_mode(self.polygon_mode);
+
+        self.polygon_mode
+    }
+
+    /// None if debug views are turned off in the settings.
+    pub fn cycle_debug_view(&mut self) -> Option<DebugView> {
+        let debug_view = self.debug_view?.next();
+        self.debug_view = Some(debug_view);
+        unsafe {
+            gl::UseProgram(self.shader_program.id);
+            self.shader_program.set_uniform("debug_view", &(debug_view as i32));
+        }
+
+        Some(debug_view)
+    }
+
+    pub fn toggle_chunk_borders(&mut self) -> bool {
+        self.show_chunk_borders = !self.show_chunk_borders;
+        self.show_chunk_borders
+    }
+
+    pub fn toggle_normals(&mut self) -> bool {
+        self.show_normals = !self.show_normals;
+        self.show_normals
+    }
+
+    pub fn spawn_block_particles(&mut self, block : [i32; 3]) {
+        if let Some(particles) = &mut self.particles {
+            unsafe { particles.burst(block); }
+        }
+    }
+
+    // once per simulation step
+    pub fn update_particles(&mut self, delta_time : f32) {
+        if let Some(particles) = &mut self.particles {
+            unsafe { particles.update(delta_time); }
+        }
     }
 
-    // called from game window loop
-    pub fn render(&mut self, projection : Matrix4<f32>, view : Matrix4<f32>, model : Matrix4<f32>) {
+    // called from game window loop, `target` is the block to outline if the player is looking at one
+    pub fn render(&mut self, projection : Matrix4<f32>, view : Matrix4<f32>, target : Option<[i32; 3]>) {
         // render
         unsafe {
             // window background colour
             gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
 
+            gl::UseProgram(self.shader_program.id);
+
             // bind textures on corresponding texture units
             gl::ActiveTexture(gl::TEXTURE0);
-            gl::BindTexture(gl::TEXTURE_2D, self.texture1.id);
+            gl::BindTexture(self.atlas.target, self.atlas.id);
 
             // pass to the shaders
-            self.shader_program.set_mat4(&CString::new("projection").unwrap(), &projection);
-            self.shader_program.set_mat4(&CString::new("view").unwrap(), &view);
+            self.shader_program.set_uniform("projection", &projection);
+            self.shader_program.set_uniform("view", &view);
 
             // draw
-            gl::BindVertexArray(self.vao);
+            for (pos, mesh) in &self.chunk_meshes {
+                let model: Matrix4<f32> = Matrix4::from_translation(pos.origin());
+                self.shader_program.set_uniform("model", &model);
 
-            self.shader_program.set_mat4(&CString::new("model").unwrap(), &model);
+                gl::BindVertexArray(mesh.vao);
+                gl::DrawArrays(
+                    gl::TRIANGLES,
+                    0,
+                    mesh.vertex_count
+                );
+            }
 
-            gl::DrawArrays(
-                gl::TRIANGLES,
-                0,
-                36
-            );
+            if let Some(particles) = &self.particles {
+                particles.render(projection, view);
+                gl::UseProgram(self.shader_program.id);
+            }
+
+            if self.show_normals {
+                self.render_normals(projection, view);
+            }
+
+            if target.is_some() || self.show_chunk_borders {
+                self.render_lines(projection, view, target);
+            }
         }
     }
 
-    unsafe fn define_attrib_pointers(&self, stride : GLsizei) {
-        let pos_attr_location = gl::GetAttribLocation(
-            self.shader_program.id,
-            CString::new("position").unwrap().as_ptr()
-        );
+    // draws the chunk meshes again, with each triangle replaced by its normal
+    unsafe fn render_normals(&self, projection : Matrix4<f32>, view : Matrix4<f32>) {
+        gl::UseProgram(self.normal_shader.id);
+        self.normal_shader.set_uniform("projection", &projection);
+        self.normal_shader.set_uniform("view", &view);
+        self.normal_shader.set_uniform("colour", &vec3(0.2, 0.4, 1.0));
 
-        let texture_attr_location = gl::GetAttribLocation(
-            self.shader_program.id,
-            CString::new("texture").unwrap().as_ptr()
-        );
+        for (pos, mesh) in &self.chunk_meshes {
+            self.normal_shader.set_uniform("model", &Matrix4::from_translation(pos.origin()));
 
-        // position attribute
-        gl::VertexAttribPointer(
-            pos_attr_location as GLuint,
-            3,
-            gl::FLOAT,
-            gl::FALSE,
-            stride,
-            std::ptr::null(),
-        );
-        gl::EnableVertexAttribArray(pos_attr_location as GLuint);
-
-        // texture attribute
-        gl::VertexAttribPointer(
-            texture_attr_location as GLuint,
-            2,
-            gl::FLOAT,
-            gl::FALSE,
-            stride,
-            (3 * mem::size_of::<GLfloat>()) as *const std::ffi::c_void,
-        );
-        gl::EnableVertexAttribArray(texture_attr_location as GLuint);
+            gl::BindVertexArray(mesh.vao);
+            gl::DrawArrays(gl::TRIANGLES, 0, mesh.vertex_count);
+        }
+
+        gl::BindVertexArray(0);
+        gl::UseProgram(self.shader_program.id);
+    }
+
+    // lines aren't affected by the polygon mode, so outlines look the same in every mode
+    unsafe fn render_lines(&self, projection : Matrix4<f32>, view : Matrix4<f32>, target : Option<[i32; 3]>) {
+        gl::UseProgram(self.line_shader.id);
+        self.line_shader.set_uniform("projection", &projection);
+        self.line_shader.set_uniform("view", &view);
+        gl::BindVertexArray(self.outline.vao);
+
+        if let Some(block) = target {
+            let [x, y, z] = block.map(|coordinate| coordinate as f32 - OUTLINE_MARGIN);
+            let model = Matrix4::from_translation(vec3(x, y, z))
+                * Matrix4::from_scale(1.0 + 2.0 * OUTLINE_MARGIN);
+
+            self.line_shader.set_uniform("model", &model);
+            self.line_shader.set_uniform("colour", &vec3(0.0, 0.0, 0.0));
+            gl::DrawArrays(gl::LINES, 0, self.outline.vertex_count);
+        }
+
+        // only chunks with something in them have a mesh, empty air isn't worth outlining
+        if self.show_chunk_borders {
+            self.line_shader.set_uniform("colour", &vec3(1.0, 0.8, 0.0));
+
+            for pos in self.chunk_meshes.keys() {
+                let model = Matrix4::from_translation(pos.origin()) * Matrix4::from_scale(CHUNK_SIZE as f32);
+                self.line_shader.set_uniform("model", &model);
+                gl::DrawArrays(gl::LINES, 0, self.outline.vertex_count);
+            }
+        }
+
+        gl::BindVertexArray(0);
+        gl::UseProgram(self.shader_program.id);
+    }
+}
+
+impl ChunkMeshes for Renderer {
+    /// Uploads a chunk's mesh, replacing any mesh already on the GPU for that chunk.
+    fn upload_chunk_mesh(&mut self, pos : ChunkPos, chunk_mesh : &ChunkMesh) {
+        self.free_chunk_mesh(pos);
+
+        if chunk_mesh.is_empty() {
+            return;
+        }
+
+        // one VBO per chunk, drawn at the chunk's origin
+        let mesh = unsafe { create_mesh(&chunk_mesh.vertices, &CHUNK_VERTEX_LAYOUT) };
+        self.chunk_meshes.insert(pos, mesh);
+    }
+
+    fn free_chunk_mesh(&mut self, pos : ChunkPos) {
+        if let Some(mesh) = self.chunk_meshes.remove(&pos) {
+            unsafe { mesh.delete(); }
+        }
+    }
+}
+
+impl Mesh {
+    unsafe fn delete(&self) {
+        gl::DeleteVertexArrays(1, &self.vao);
+        gl::DeleteBuffers(1, &self.vbo);
+    }
+}
+
+impl Drop for Renderer {
+    fn drop(&mut self) {
+        unsafe {
+            for mesh in self.chunk_meshes.values() {
+                mesh.delete();
+            }
+            self.outline.delete();
+        }
+    }
+}
+
+// the 12 edges of a unit cube as pairs of points for GL_LINES
+unsafe fn create_outline_mesh() -> Mesh {
+    let mut vertices: Vec<f32> = Vec::with_capacity(24 * 3);
+    for axis in 0..3 {
+        // each axis has four edges running along it, one from each corner of the opposite face
+        for (a, b) in [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)] {
+            for along in [0.0, 1.0] {
+                let mut point = [0.0; 3];
+                point[axis] = along;
+                point[(axis + 1) % 3] = a;
+                point[(axis + 2) % 3] = b;
+                vertices.extend_from_slice(&point);
+            }
+        }
+    }
+
+    create_mesh(&vertices, &OUTLINE_VERTEX_LAYOUT)
+}
+
+// upload interleaved vertex data into a new VAO
+unsafe fn create_mesh(vertices : &[f32], layout : &VertexLayout) -> Mesh {
+    // Generate and bind vertex array object (VAO)
+    let mut vao = 0;
+    gl::GenVertexArrays(1, &mut vao);
+    gl::BindVertexArray(vao);
+
+    // Generate and bind vertex buffer object (VBO)
+    let vbo = define_buffer(
+        gl::ARRAY_BUFFER,
+        vertices,
+        gl::STATIC_DRAW
+    );
+
+    // define attribute pointers
+    layout.apply();
+
+    gl::BindVertexArray(0);
+
+    Mesh {
+        vao,
+        vbo,
+        vertex_count: layout.vertex_count(vertices),
     }
 }
 
@@ -132,11 +421,29 @@ fn define_buffer<T>(target: GLenum, array : &[T], draw_type : GLenum) -> GLuint
         gl::BindBuffer(target, buffer_object);
         gl::BufferData(
             target,
-            (array.len() * std::mem::size_of::<T>()) as isize,
+            mem::size_of_val(array) as isize,
             array.as_ptr() as *const std::ffi::c_void,
             draw_type,
         );
     }
 
     buffer_object
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn debug_views_cycle_back_to_textured() {
+        let mut view = DebugView::Textured;
+        let mut seen = Vec::new();
+        for _ in 0..4 {
+            seen.push(view);
+            view = view.next();
+        }
+
+        assert_eq!(view, DebugView::Textured);
+        assert_eq!(seen, [DebugView::Textured, DebugView::Normals, DebugView::TextureCoordinates, DebugView::Depth]);
+    }
+}
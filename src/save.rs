@@ -0,0 +1,522 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use crate::block::BlockId;
+use crate::chunk::{Chunk, ChunkPos, CHUNK_VOLUME};
+use crate::terrain::WorldType;
+
+// bump whenever the layout below changes, older files keep loading, newer ones are refused
+pub const FORMAT_VERSION : u32 = 1;
+
+const WORLD_MAGIC : &[u8; 4] = b"GLVW";
+const REGION_MAGIC : &[u8; 4] = b"GLVR";
+const HEADER_FILE : &str = "world.dat";
+const REGION_DIRECTORY : &str = "region";
+const REGION_EXTENSION : &str = "region";
+// a save is built up next to the world's directory and then swapped in for it
+const STAGING_SUFFIX : &str = ".saving";
+const PREVIOUS_SUFFIX : &str = ".previous";
+
+// regions group REGION_SIZE chunks along each axis into one file
+const REGION_SIZE : i32 = 8;
+
+type RegionPos = (i32, i32, i32);
+
+/*
+ * On-disk layout, all integers little-endian:
+ *
+ * world.dat:           magic "GLVW", format version u32, world type u8 (0 demo, 1 terrain), seed u64
+ * region/r.X.Y.Z.region: magic "GLVR", format version u32, chunk count u32, then per chunk:
+ *                      chunk x/y/z inside the region u8 x3, data length u32, run-length encoded blocks
+ *
+ * Blocks are run-length encoded as (run length u16, block id u8) pairs in chunk index order.
+ */
+
+#[derive(Debug)]
+pub enum SaveError {
+    Io(io::Error),
+    /// The file doesn't start with the expected magic bytes.
+    NotAWorld,
+    /// The file was written by a newer build of the game.
+    UnsupportedVersion { found : u32, supported : u32 },
+    Corrupt(String),
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SaveError::Io(error) => write!(f, "world file I/O failed: {}", error),
+            SaveError::NotAWorld => write!(f, "not a world save file"),
+            SaveError::UnsupportedVersion { found, supported } => write!(
+                f,
+                "world was saved in format version {}, but this build only reads up to version {}; update the game to load it",
+                found,
+                supported
+            ),
+            SaveError::Corrupt(reason) => write!(f, "world save is corrupt: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for SaveError {}
+
+impl From<io::Error> for SaveError {
+    fn from(error : io::Error) -> Self {
+        SaveError::Io(error)
+    }
+}
+
+/// Everything stored in a save directory.
+pub struct SaveData {
+    pub world_type : WorldType,
+    pub chunks : HashMap<ChunkPos, Chunk>,
+}
+
+// the longest run-length encoding a chunk can have, every block its own run
+const MAX_CHUNK_DATA : usize = CHUNK_VOLUME * 3;
+
+/// The whole save is written into a sibling directory and only swapped in for `directory`
+/// once every file is on disk, so a crash part way through never mixes old and new regions.
+/// A crash during the swap itself is finished off by `save_exists` or the next save.
+pub fn write_world(directory : &Path, world_type : WorldType, chunks : &[(ChunkPos, &Chunk)]) -> Result<(), SaveError> {
+    finish_interrupted_save(directory)?;
+
+    // left over from a save that failed before it was complete
+    let staging = sibling(directory, STAGING_SUFFIX);
+    if staging.exists() {
+        fs::remove_dir_all(&staging)?;
+    }
+    let region_directory = staging.join(REGION_DIRECTORY);
+    fs::create_dir_all(&region_directory)?;
+
+    let mut regions: HashMap<RegionPos, Vec<(ChunkPos, &Chunk)>> = HashMap::new();
+    for &(pos, chunk) in chunks {
+        regions.entry(region_of(pos)).or_default().push((pos, chunk));
+    }
+
+    for (region, chunks) in regions {
+        write_file(&region_directory.join(region_file_name(region)), |file| {
+            file.write_all(REGION_MAGIC)?;
+            file.write_all(&FORMAT_VERSION.to_le_bytes())?;
+            file.write_all(&(chunks.len() as u32).to_le_bytes())?;
+
+            for (pos, chunk) in &chunks {
+                let data = encode_blocks(chunk.blocks());
+                file.write_all(&[
+                    pos.x.rem_euclid(REGION_SIZE) as u8,
+                    pos.y.rem_euclid(REGION_SIZE) as u8,
+                    pos.z.rem_euclid(REGION_SIZE) as u8,
+                ])?;
+                file.write_all(&(data.len() as u32).to_le_bytes())?;
+                file.write_all(&data)?;
+            }
+
+            Ok(())
+        })?;
+    }
+
+    // the header goes last, a staged save without one never gets swapped in
+    write_file(&staging.join(HEADER_FILE), |header| {
+        header.write_all(WORLD_MAGIC)?;
+        header.write_all(&FORMAT_VERSION.to_le_bytes())?;
+        let (type_tag, seed) = match world_type {
+            WorldType::Demo => (0u8, 0u64),
+            WorldType::Terrain { seed } => (1u8, seed),
+        };
+        header.write_all(&[type_tag])?;
+        header.write_all(&seed.to_le_bytes())
+    })?;
+
+    // a directory can't be renamed over one that isn't empty, so the old save steps aside first
+    let previous = sibling(directory, PREVIOUS_SUFFIX);
+    if directory.exists() {
+        fs::rename(directory, &previous)?;
+    }
+    fs::rename(&staging, directory)?;
+    if previous.exists() {
+        fs::remove_dir_all(&previous)?;
+    }
+    Ok(())
+}
+
+/// Whether there's a world saved in `directory`, once a save that crashed while being swapped in is finished.
+pub fn save_exists(directory : &Path) -> bool {
+    if let Err(error) = finish_interrupted_save(directory) {
+        println!("Couldn't finish the last save: {}", error);
+    }
+    directory.exists()
+}
+
+// puts back whichever of the staged and previous saves is complete, when a crash left neither in place
+fn finish_interrupted_save(directory : &Path) -> io::Result<()> {
+    let staging = sibling(directory, STAGING_SUFFIX);
+    let previous = sibling(directory, PREVIOUS_SUFFIX);
+
+    if !directory.exists() {
+        if staging.join(HEADER_FILE).exists() {
+            fs::rename(&staging, directory)?;
+        } else if previous.exists() {
+            fs::rename(&previous, directory)?;
+        }
+    }
+    if previous.exists() {
+        fs::remove_dir_all(&previous)?;
+    }
+    Ok(())
+}
+
+// `saves/world` becomes `saves/world<suffix>`
+fn sibling(directory : &Path, suffix : &str) -> PathBuf {
+    let mut name = directory.as_os_str().to_owned();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+fn write_file(path : &Path, contents : impl FnOnce(&mut BufWriter<File>) -> io::Result<()>) -> Result<(), SaveError> {
+    let mut file = BufWriter::new(File::create(path)?);
+    contents(&mut file)?;
+    file.flush()?;
+    // make sure the data is on disk before the save is swapped in
+    file.get_ref().sync_all()?;
+    Ok(())
+}
+
+pub fn read_world(directory : &Path) -> Result<SaveData, SaveError> {
+    let mut header = BufReader::new(File::open(directory.join(HEADER_FILE))?);
+    read_preamble(&mut header, WORLD_MAGIC)?;
+
+    let type_tag = read_u8(&mut header)?;
+    let seed = read_u64(&mut header)?;
+    let world_type = match type_tag {
+        0 => WorldType::Demo,
+        1 => WorldType::Terrain { seed },
+        other => return Err(SaveError::Corrupt(format!("unknown world type {}", other))),
+    };
+
+    let mut chunks = HashMap::new();
+    let region_directory = directory.join(REGION_DIRECTORY);
+    if region_directory.is_dir() {
+        for entry in fs::read_dir(region_directory)? {
+            let path = entry?.path();
+            if path.extension().and_then(|extension| extension.to_str()) != Some(REGION_EXTENSION) {
+                continue;
+            }
+
+            let region = parse_region_file_name(&path)
+                .ok_or_else(|| SaveError::Corrupt(format!("unexpected region file {}", path.display())))?;
+            read_region(&path, region, &mut chunks)?;
+        }
+    }
+
+    Ok(SaveData {
+        world_type,
+        chunks,
+    })
+}
+
+fn read_region(path : &Path, region : RegionPos, chunks : &mut HashMap<ChunkPos, Chunk>) -> Result<(), SaveError> {
+    let mut file = BufReader::new(File::open(path)?);
+    read_preamble(&mut file, REGION_MAGIC)?;
+
+    let count = read_u32(&mut file)?;
+    for _ in 0..count {
+        let mut local = [0u8; 3];
+        file.read_exact(&mut local)?;
+        if local.iter().any(|&coordinate| coordinate as i32 >= REGION_SIZE) {
+            return Err(SaveError::Corrupt(format!("chunk outside its region in {}", path.display())));
+        }
+
+        // checked before allocating, a corrupt length could otherwise ask for gigabytes
+        let length = read_u32(&mut file)? as usize;
+        if length > MAX_CHUNK_DATA {
+            return Err(SaveError::Corrupt(format!("chunk data is {} bytes in {}", length, path.display())));
+        }
+        let mut data = vec![0u8; length];
+        file.read_exact(&mut data)?;
+
+        let pos = ChunkPos::new(
+            region.0 * REGION_SIZE + local[0] as i32,
+            region.1 * REGION_SIZE + local[1] as i32,
+            region.2 * REGION_SIZE + local[2] as i32,
+        );
+        chunks.insert(pos, Chunk::from_blocks(&decode_blocks(&data)?));
+    }
+
+    Ok(())
+}
+
+// magic bytes and format version shared by every file
+fn read_preamble<R : Read>(reader : &mut R, magic : &[u8; 4]) -> Result<(), SaveError> {
+    let mut found = [0u8; 4];
+    reader.read_exact(&mut found)?;
+    if &found != magic {
+        return Err(SaveError::NotAWorld);
+    }
+
+    let version = read_u32(reader)?;
+    if version > FORMAT_VERSION {
+        return Err(SaveError::UnsupportedVersion {
+            found: version,
+            supported: FORMAT_VERSION,
+        });
+    }
+
+    Ok(())
+}
+
+fn read_u8<R : Read>(reader : &mut R) -> io::Result<u8> {
+    let mut bytes = [0u8; 1];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes[0])
+}
+
+fn read_u32<R : Read>(reader : &mut R) -> io::Result<u32> {
+    let mut bytes = [0u8; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64<R : Read>(reader : &mut R) -> io::Result<u64> {
+    let mut bytes = [0u8; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn region_of(pos : ChunkPos) -> RegionPos {
+    (
+        pos.x.div_euclid(REGION_SIZE),
+        pos.y.div_euclid(REGION_SIZE),
+        pos.z.div_euclid(REGION_SIZE),
+    )
+}
+
+fn region_file_name((x, y, z) : RegionPos) -> String {
+    format!("r.{}.{}.{}.{}", x, y, z, REGION_EXTENSION)
+}
+
+fn parse_region_file_name(path : &Path) -> Option<RegionPos> {
+    let name = path.file_stem()?.to_str()?;
+    let mut parts = name.strip_prefix("r.")?.split('.');
+
+    let x = parts.next()?.parse().ok()?;
+    let y = parts.next()?.parse().ok()?;
+    let z = parts.next()?.parse().ok()?;
+
+    match parts.next() {
+        None => Some((x, y, z)),
+        Some(_) => None,
+    }
+}
+
+pub fn encode_blocks(blocks : &[BlockId]) -> Vec<u8> {
+    let mut data = Vec::new();
+    let mut iter = blocks.iter().peekable();
+
+    while let Some(&block) = iter.next() {
+        let mut run: u16 = 1;
+        while run < u16::MAX && iter.peek() == Some(&&block) {
+            iter.next();
+            run += 1;
+        }
+
+        data.extend_from_slice(&run.to_le_bytes());
+        data.push(block);
+    }
+
+    data
+}
+
+pub fn decode_blocks(data : &[u8]) -> Result<Vec<BlockId>, SaveError> {
+    if !data.len().is_multiple_of(3) {
+        return Err(SaveError::Corrupt("truncated block data".to_string()));
+    }
+
+    let mut blocks = Vec::with_capacity(CHUNK_VOLUME);
+    for run in data.chunks(3) {
+        let length = u16::from_le_bytes([run[0], run[1]]) as usize;
+        // checked before growing, so a corrupt file can't make us allocate more than a chunk
+        if blocks.len() + length > CHUNK_VOLUME {
+            return Err(SaveError::Corrupt(format!("chunk has more than {} blocks", CHUNK_VOLUME)));
+        }
+        blocks.extend(std::iter::repeat_n(run[2], length));
+    }
+
+    if blocks.len() != CHUNK_VOLUME {
+        return Err(SaveError::Corrupt(format!("chunk has {} blocks, expected {}", blocks.len(), CHUNK_VOLUME)));
+    }
+
+    Ok(blocks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use crate::block::{BRICK, WOOD};
+
+    // a fresh directory per test so tests can run in parallel
+    fn temp_directory(name : &str) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!("glutin_demo_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        directory
+    }
+
+    fn sample_chunk() -> Chunk {
+        let mut chunk = Chunk::default();
+        for x in 0..16 {
+            chunk.set(x, 0, 0, BRICK);
+        }
+        chunk.set(3, 7, 9, WOOD);
+        chunk
+    }
+
+    #[test]
+    fn run_length_encoding_round_trips() {
+        let chunk = sample_chunk();
+        let encoded = encode_blocks(chunk.blocks());
+
+        assert_eq!(decode_blocks(&encoded).unwrap(), chunk.blocks());
+        // a mostly empty chunk should shrink to a handful of runs
+        assert!(encoded.len() < 32);
+    }
+
+    #[test]
+    fn empty_chunk_is_a_single_run() {
+        assert_eq!(encode_blocks(Chunk::default().blocks()).len(), 3);
+    }
+
+    #[test]
+    fn world_round_trips() {
+        let directory = temp_directory("round_trip");
+        let chunk = sample_chunk();
+        let positions = [ChunkPos::new(0, 0, 0), ChunkPos::new(-1, 2, 9), ChunkPos::new(17, -3, -8)];
+        let chunks: Vec<(ChunkPos, &Chunk)> = positions.iter().map(|&pos| (pos, &chunk)).collect();
+
+        write_world(&directory, WorldType::Terrain { seed: 1234 }, &chunks).unwrap();
+        let loaded = read_world(&directory).unwrap();
+
+        assert_eq!(loaded.world_type, WorldType::Terrain { seed: 1234 });
+        assert_eq!(loaded.chunks.len(), positions.len());
+        for pos in positions {
+            assert_eq!(loaded.chunks[&pos].blocks(), chunk.blocks());
+        }
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn saving_replaces_the_previous_save() {
+        let directory = temp_directory("replace");
+        let chunk = sample_chunk();
+        let far = ChunkPos::new(40, 0, 0);
+
+        write_world(&directory, WorldType::Demo, &[(ChunkPos::new(0, 0, 0), &chunk), (far, &chunk)]).unwrap();
+        write_world(&directory, WorldType::Demo, &[(ChunkPos::new(0, 0, 0), &chunk)]).unwrap();
+
+        // the far chunk's region is gone, and nothing from the swap is left behind
+        let loaded = read_world(&directory).unwrap();
+        assert_eq!(loaded.chunks.len(), 1);
+        let files: Vec<_> = fs::read_dir(directory.join(REGION_DIRECTORY)).unwrap().collect();
+        assert_eq!(files.len(), 1);
+        assert!(!sibling(&directory, STAGING_SUFFIX).exists());
+        assert!(!sibling(&directory, PREVIOUS_SUFFIX).exists());
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn oversized_chunk_data_is_corrupt() {
+        let directory = temp_directory("oversized");
+        write_world(&directory, WorldType::Demo, &[(ChunkPos::new(0, 0, 0), &sample_chunk())]).unwrap();
+
+        // patch the first chunk's data length, after the preamble, chunk count and position
+        let region = directory.join(REGION_DIRECTORY).join(region_file_name((0, 0, 0)));
+        let mut bytes = fs::read(&region).unwrap();
+        bytes[15..19].copy_from_slice(&u32::MAX.to_le_bytes());
+        fs::write(&region, bytes).unwrap();
+
+        assert!(matches!(read_world(&directory), Err(SaveError::Corrupt(_))));
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn interrupted_swaps_are_finished_or_rolled_back() {
+        let directory = temp_directory("interrupted");
+        let staging = sibling(&directory, STAGING_SUFFIX);
+        let previous = sibling(&directory, PREVIOUS_SUFFIX);
+        let chunk = sample_chunk();
+
+        // crashed after stepping the old save aside, with the new one complete
+        write_world(&directory, WorldType::Demo, &[]).unwrap();
+        fs::rename(&directory, &previous).unwrap();
+        write_world(&staging, WorldType::Demo, &[(ChunkPos::new(0, 0, 0), &chunk)]).unwrap();
+        assert!(save_exists(&directory));
+        assert_eq!(read_world(&directory).unwrap().chunks.len(), 1);
+        assert!(!previous.exists());
+
+        // crashed before the new save's header was written, the old one comes back
+        fs::rename(&directory, &previous).unwrap();
+        fs::create_dir_all(staging.join(REGION_DIRECTORY)).unwrap();
+        assert!(save_exists(&directory));
+        assert_eq!(read_world(&directory).unwrap().chunks.len(), 1);
+
+        // the next save clears out the half written one
+        write_world(&directory, WorldType::Demo, &[]).unwrap();
+        assert!(read_world(&directory).unwrap().chunks.is_empty());
+        assert!(!staging.exists());
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn overlong_runs_are_corrupt() {
+        // far more blocks than a chunk holds, caught before they're all decoded
+        let data: Vec<u8> = std::iter::repeat_n([0xff, 0xff, 1], 1000).flatten().collect();
+
+        assert!(matches!(decode_blocks(&data), Err(SaveError::Corrupt(_))));
+    }
+
+    #[test]
+    fn newer_format_is_refused() {
+        let directory = temp_directory("newer_format");
+        write_world(&directory, WorldType::Demo, &[]).unwrap();
+
+        // patch the version field in the header
+        let header = directory.join(HEADER_FILE);
+        let mut bytes = fs::read(&header).unwrap();
+        bytes[4..8].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+        fs::write(&header, bytes).unwrap();
+
+        match read_world(&directory) {
+            Err(SaveError::UnsupportedVersion { found, supported }) => {
+                assert_eq!(found, FORMAT_VERSION + 1);
+                assert_eq!(supported, FORMAT_VERSION);
+            }
+            _ => panic!("expected an unsupported version error"),
+        }
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn other_files_are_not_worlds() {
+        let directory = temp_directory("not_a_world");
+        fs::create_dir_all(&directory).unwrap();
+        fs::write(directory.join(HEADER_FILE), b"hello world").unwrap();
+
+        assert!(matches!(read_world(&directory), Err(SaveError::NotAWorld)));
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn region_file_names_round_trip() {
+        let region = (-3, 0, 12);
+        let path = PathBuf::from(region_file_name(region));
+
+        assert_eq!(parse_region_file_name(&path), Some(region));
+    }
+}
@@ -0,0 +1,493 @@
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use glutin_opengl_demo::PolygonMode;
+use serde::Deserialize;
+use toml::{Table, Value};
+use crate::block::{block_by_name, is_solid, BlockId};
+use crate::game_window::WindowMode;
+use crate::terrain::WorldType;
+
+// read when no --config is given, the defaults are used if it doesn't exist
+pub const DEFAULT_SETTINGS_FILE : &str = "config/settings.toml";
+
+const USAGE : &str = "usage: glutin_opengl_demo [--config <file>] [--set <key>=<value>]...";
+
+/// Everything that can be changed without recompiling, loaded from a TOML file.
+/// Any key the file leaves out keeps its default.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    // the file these came from, window mode changes are written back to it
+    #[serde(skip)]
+    pub path : Option<PathBuf>,
+    pub window : WindowSettings,
+    pub camera : CameraSettings,
+    pub graphics : GraphicsSettings,
+    pub timing : TimingSettings,
+    pub world : WorldSettings,
+    pub assets : AssetSettings,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WindowSettings {
+    pub title : String,
+    pub width : u32,
+    pub height : u32,
+    pub vsync : bool,
+    pub mode : WindowMode,
+    // index into the monitor list for fullscreen modes, the primary monitor if it's out of range
+    pub monitor : usize,
+    // exclusive fullscreen resolution, the monitor's largest if left out
+    pub fullscreen_resolution : Option<[u32; 2]>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CameraSettings {
+    // vertical field of view in degrees
+    pub fov : f32,
+    pub mouse_sensitivity : f32,
+    // 0 turns smoothing off, closer to 1 is smoother but laggier
+    pub mouse_smoothing : f32,
+    pub invert_y : bool,
+    pub movement_speed : f32,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GraphicsSettings {
+    pub polygon_mode : PolygonMode,
+    pub clear_colour : [f32; 3],
+    // builds the block shader with the normal, texture coordinate and depth views
+    pub debug_views : bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TimingSettings {
+    // simulation steps per second, independent of the frame rate
+    pub tick_rate : u32,
+    // 0 leaves the frame rate to vsync, or caps it at 500 with vsync off
+    pub max_fps : u32,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WorldSettings {
+    // "terrain" or "demo", the hand-built test scene
+    pub generator : String,
+    pub seed : u64,
+    pub save_directory : PathBuf,
+    // how far away blocks can be broken or placed, and what gets placed
+    pub block_reach : f32,
+    pub placed_block : String,
+    // chunk streaming, distances are in chunks
+    pub view_distance : i32,
+    // chunk generation and meshing jobs queued on the worker threads at once
+    pub max_chunk_jobs_in_flight : usize,
+    // uploading too many meshes in one frame causes visible hitches
+    pub max_chunk_uploads_per_frame : usize,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AssetSettings {
+    pub block_vertex_shader : PathBuf,
+    pub block_fragment_shader : PathBuf,
+    pub line_vertex_shader : PathBuf,
+    pub line_fragment_shader : PathBuf,
+    // draws face normals as lines, coloured by the line fragment shader
+    pub normal_vertex_shader : PathBuf,
+    pub normal_geometry_shader : PathBuf,
+    // dust thrown up by broken blocks, simulated by a compute shader on GL 4.3
+    pub particle_compute_shader : PathBuf,
+    pub particle_vertex_shader : PathBuf,
+    pub texture_directory : PathBuf,
+    pub bindings : PathBuf,
+    // rebuild shaders when their files are saved, without restarting
+    pub hot_reload_shaders : bool,
+}
+
+impl Default for WindowSettings {
+    fn default() -> Self {
+        WindowSettings {
+            title: "OpenGL Demo".to_string(),
+            width: 800,
+            height: 600,
+            vsync: true,
+            mode: WindowMode::Windowed,
+            monitor: 0,
+            fullscreen_resolution: None,
+        }
+    }
+}
+
+impl Default for CameraSettings {
+    fn default() -> Self {
+        CameraSettings {
+            fov: 45.0,
+            mouse_sensitivity: 0.1,
+            mouse_smoothing: 0.0,
+            invert_y: false,
+            movement_speed: 50.0,
+        }
+    }
+}
+
+impl Default for GraphicsSettings {
+    fn default() -> Self {
+        GraphicsSettings {
+            polygon_mode: PolygonMode::Line,
+            clear_colour: [0.7, 0.7, 0.8],
+            debug_views: true,
+        }
+    }
+}
+
+impl Default for TimingSettings {
+    fn default() -> Self {
+        TimingSettings {
+            tick_rate: 60,
+            max_fps: 0,
+        }
+    }
+}
+
+impl Default for WorldSettings {
+    fn default() -> Self {
+        WorldSettings {
+            generator: "terrain".to_string(),
+            seed: 20231015,
+            save_directory: PathBuf::from("saves/world"),
+            block_reach: 8.0,
+            placed_block: "brick".to_string(),
+            view_distance: 6,
+            max_chunk_jobs_in_flight: 32,
+            max_chunk_uploads_per_frame: 4,
+        }
+    }
+}
+
+impl Default for AssetSettings {
+    fn default() -> Self {
+        AssetSettings {
+            block_vertex_shader: PathBuf::from("shaders/shader.vs"),
+            block_fragment_shader: PathBuf::from("shaders/shader.fs"),
+            line_vertex_shader: PathBuf::from("shaders/line.vs"),
+            line_fragment_shader: PathBuf::from("shaders/line.fs"),
+            normal_vertex_shader: PathBuf::from("shaders/normals.vs"),
+            normal_geometry_shader: PathBuf::from("shaders/normals.gs"),
+            particle_compute_shader: PathBuf::from("shaders/particles.cs"),
+            particle_vertex_shader: PathBuf::from("shaders/particles.vs"),
+            texture_directory: PathBuf::from("resources/textures"),
+            bindings: PathBuf::from("config/bindings.cfg"),
+            hot_reload_shaders: true,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum SettingsError {
+    Io { path : PathBuf, error : std::io::Error },
+    /// The file isn't valid TOML, or a value has the wrong type.
+    Parse { source : String, message : String },
+    /// A value parsed but is out of range.
+    Invalid { key : &'static str, message : String },
+    Arguments(String),
+}
+
+impl fmt::Display for SettingsError {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SettingsError::Io { path, error } => write!(f, "couldn't read {}: {}", path.display(), error),
+            SettingsError::Parse { source, message } => write!(f, "{}: {}", source, message.trim_end()),
+            SettingsError::Invalid { key, message } => write!(f, "invalid setting `{}`: {}", key, message),
+            SettingsError::Arguments(message) => write!(f, "{}\n{}", message, USAGE),
+        }
+    }
+}
+
+impl std::error::Error for SettingsError {}
+
+impl Settings {
+    /// Loads the settings file named on the command line (or the default one), then applies
+    /// any `--set key=value` overrides, e.g. `--set window.width=1280 --set world.seed=7`.
+    pub fn from_args(args : impl Iterator<Item = String>) -> Result<Self, SettingsError> {
+        let mut config = None;
+        let mut overrides = Vec::new();
+
+        let mut args = args;
+        while let Some(arg) = args.next() {
+            let mut value = |name : &str| {
+                args.next().ok_or_else(|| SettingsError::Arguments(format!("{} needs a value", name)))
+            };
+
+            match arg.as_str() {
+                "--config" => config = Some(PathBuf::from(value("--config")?)),
+                "--set" => overrides.push(value("--set")?),
+                _ => return Err(SettingsError::Arguments(format!("unknown argument '{}'", arg))),
+            }
+        }
+
+        // a missing default file is fine, a missing file that was asked for isn't
+        let text = match &config {
+            Some(path) => read(path)?,
+            None if Path::new(DEFAULT_SETTINGS_FILE).exists() => read(Path::new(DEFAULT_SETTINGS_FILE))?,
+            None => String::new(),
+        };
+        let path = config.unwrap_or_else(|| PathBuf::from(DEFAULT_SETTINGS_FILE));
+
+        let mut settings = Settings::parse(&text, &path.display().to_string(), &overrides)?;
+        settings.path = Some(path);
+        Ok(settings)
+    }
+
+    /// Records the window mode in the settings file so the game starts in it next time.
+    /// The rest of the file, comments included, is left as it was.
+    pub fn save_window_mode(&self, mode : WindowMode) -> Result<(), SettingsError> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+
+        let text = if path.exists() { read(path)? } else { String::new() };
+        let mut document: toml_edit::DocumentMut = text.parse().map_err(|error : toml_edit::TomlError| {
+            SettingsError::Parse {
+                source: path.display().to_string(),
+                message: error.to_string(),
+            }
+        })?;
+
+        if !document.contains_table("window") {
+            document["window"] = toml_edit::table();
+        }
+        document["window"]["mode"] = toml_edit::value(mode.name());
+
+        fs::write(path, document.to_string()).map_err(|error| SettingsError::Io { path: path.clone(), error })
+    }
+
+    /// Parses settings from TOML text, with each override given as `dotted.key=value`.
+    pub fn parse(text : &str, source : &str, overrides : &[String]) -> Result<Self, SettingsError> {
+        let mut table: Table = toml::from_str(text).map_err(|error| SettingsError::Parse {
+            source: source.to_string(),
+            message: error.to_string(),
+        })?;
+
+        for assignment in overrides {
+            apply_override(&mut table, assignment)?;
+        }
+
+        // going through a Value rather than the text keeps overrides and file values together,
+        // and its errors name the key that was wrong
+        let settings = Settings::deserialize(Value::Table(table)).map_err(|error| SettingsError::Parse {
+            source: source.to_string(),
+            message: error.to_string(),
+        })?;
+
+        settings.validate()?;
+        Ok(settings)
+    }
+
+    fn validate(&self) -> Result<(), SettingsError> {
+        let invalid = |key, message : &str| Err(SettingsError::Invalid { key, message: message.to_string() });
+
+        if self.window.width == 0 || self.window.height == 0 {
+            return invalid(if self.window.width == 0 { "window.width" } else { "window.height" }, "must be above 0");
+        }
+        if self.window.fullscreen_resolution.is_some_and(|[width, height]| width == 0 || height == 0) {
+            return invalid("window.fullscreen_resolution", "must be above 0");
+        }
+        if !(1.0..=179.0).contains(&self.camera.fov) {
+            return invalid("camera.fov", "must be between 1 and 179 degrees");
+        }
+        if self.camera.mouse_sensitivity <= 0.0 {
+            return invalid("camera.mouse_sensitivity", "must be above 0");
+        }
+        if !(0.0..1.0).contains(&self.camera.mouse_smoothing) {
+            return invalid("camera.mouse_smoothing", "must be at least 0 and below 1");
+        }
+        if self.camera.movement_speed <= 0.0 {
+            return invalid("camera.movement_speed", "must be above 0");
+        }
+        if self.graphics.clear_colour.iter().any(|channel| !(0.0..=1.0).contains(channel)) {
+            return invalid("graphics.clear_colour", "each channel must be between 0 and 1");
+        }
+        if !(1..=1000).contains(&self.timing.tick_rate) {
+            return invalid("timing.tick_rate", "must be between 1 and 1000");
+        }
+        // u32, so only the top of the range needs checking
+        if self.timing.max_fps > 500 {
+            return invalid("timing.max_fps", "must be 0 to leave it to vsync, or between 1 and 500");
+        }
+        if self.world.generator != "terrain" && self.world.generator != "demo" {
+            return invalid("world.generator", "must be \"terrain\" or \"demo\"");
+        }
+        if self.world.block_reach <= 0.0 {
+            return invalid("world.block_reach", "must be above 0");
+        }
+        if block_by_name(&self.world.placed_block).filter(|&block| is_solid(block)).is_none() {
+            return invalid("world.placed_block", &format!("'{}' isn't a solid block", self.world.placed_block));
+        }
+        if !(1..=32).contains(&self.world.view_distance) {
+            return invalid("world.view_distance", "must be between 1 and 32 chunks");
+        }
+        if self.world.max_chunk_jobs_in_flight == 0 {
+            return invalid("world.max_chunk_jobs_in_flight", "must be above 0");
+        }
+        if self.world.max_chunk_uploads_per_frame == 0 {
+            return invalid("world.max_chunk_uploads_per_frame", "must be above 0");
+        }
+
+        Ok(())
+    }
+
+    pub fn world_type(&self) -> WorldType {
+        match self.world.generator.as_str() {
+            "demo" => WorldType::Demo,
+            _ => WorldType::Terrain { seed: self.world.seed },
+        }
+    }
+
+    // checked by validate
+    pub fn placed_block(&self) -> BlockId {
+        block_by_name(&self.world.placed_block).unwrap_or_default()
+    }
+}
+
+fn read(path : &Path) -> Result<String, SettingsError> {
+    fs::read_to_string(path).map_err(|error| SettingsError::Io { path: path.to_path_buf(), error })
+}
+
+// set `dotted.key=value` in the table, the value is read as TOML and falls back to a plain string
+fn apply_override(table : &mut Table, assignment : &str) -> Result<(), SettingsError> {
+    let Some((key, value)) = assignment.split_once('=') else {
+        return Err(SettingsError::Arguments(format!("expected key=value, got '{}'", assignment)));
+    };
+
+    let value = toml::from_str::<Table>(&format!("value = {}", value.trim()))
+        .ok()
+        .and_then(|mut parsed| parsed.remove("value"))
+        .unwrap_or_else(|| Value::String(value.trim().to_string()));
+
+    let mut path: Vec<&str> = key.trim().split('.').collect();
+    let last = path.pop().unwrap_or_default();
+
+    let mut current = table;
+    for part in path {
+        let entry = current
+            .entry(part.to_string())
+            .or_insert_with(|| Value::Table(Table::new()));
+        current = match entry {
+            Value::Table(inner) => inner,
+            _ => return Err(SettingsError::Arguments(format!("'{}' in '{}' isn't a table", part, key))),
+        };
+    }
+    current.insert(last.to_string(), value);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text : &str, overrides : &[&str]) -> Result<Settings, SettingsError> {
+        let overrides: Vec<String> = overrides.iter().map(|o| o.to_string()).collect();
+        Settings::parse(text, "test.toml", &overrides)
+    }
+
+    #[test]
+    fn empty_file_gives_defaults() {
+        let settings = parse("", &[]).unwrap();
+
+        assert_eq!(settings.window.width, 800);
+        assert_eq!(settings.graphics.polygon_mode, PolygonMode::Line);
+        assert_eq!(settings.world_type(), WorldType::Terrain { seed: 20231015 });
+    }
+
+    #[test]
+    fn shipped_settings_file_is_valid() {
+        let text = fs::read_to_string(DEFAULT_SETTINGS_FILE).unwrap();
+
+        assert!(parse(&text, &[]).is_ok());
+    }
+
+    #[test]
+    fn file_values_replace_defaults() {
+        let text = "
+            [window]
+            width = 1280
+            [graphics]
+            polygon_mode = \"fill\"
+            [world]
+            generator = \"demo\"
+        ";
+        let settings = parse(text, &[]).unwrap();
+
+        assert_eq!(settings.window.width, 1280);
+        assert_eq!(settings.window.height, 600);
+        assert_eq!(settings.window.mode, WindowMode::Windowed);
+        assert_eq!(settings.graphics.polygon_mode, PolygonMode::Fill);
+        assert_eq!(settings.world_type(), WorldType::Demo);
+    }
+
+    #[test]
+    fn overrides_win_over_the_file() {
+        let settings = parse(
+            "[window]\nwidth = 1280",
+            &["window.width=1920", "window.title=Big Window", "world.placed_block=wood"],
+        ).unwrap();
+
+        assert_eq!(settings.window.width, 1920);
+        assert_eq!(settings.window.title, "Big Window");
+        assert_eq!(settings.placed_block(), crate::block::WOOD);
+    }
+
+    #[test]
+    fn errors_name_the_bad_key() {
+        let wrong_type = parse("[window]\nwidth = \"wide\"", &[]).unwrap_err().to_string();
+        assert!(wrong_type.contains("window.width"), "{}", wrong_type);
+
+        let unknown = parse("[camera]\nfeild_of_view = 90", &[]).unwrap_err().to_string();
+        assert!(unknown.contains("feild_of_view"), "{}", unknown);
+
+        let out_of_range = parse("", &["camera.fov=500"]).unwrap_err().to_string();
+        assert!(out_of_range.contains("camera.fov"), "{}", out_of_range);
+
+        let too_fast = parse("[timing]\nmax_fps = 10000", &[]).unwrap_err().to_string();
+        assert!(too_fast.contains("timing.max_fps"), "{}", too_fast);
+        assert!(parse("", &["timing.max_fps=500"]).is_ok());
+
+        let not_a_block = parse("[world]\nplaced_block = \"air\"", &[]).unwrap_err().to_string();
+        assert!(not_a_block.contains("world.placed_block"), "{}", not_a_block);
+    }
+
+    #[test]
+    fn window_mode_is_written_back_without_touching_the_rest() {
+        let path = std::env::temp_dir().join(format!("settings_test_{}.toml", std::process::id()));
+        fs::write(&path, "# keep this comment\n[window]\nwidth = 1024 # and this one\nmode = \"windowed\"\n").unwrap();
+
+        let mut settings = Settings::parse(&read(&path).unwrap(), "test", &[]).unwrap();
+        settings.path = Some(path.clone());
+        settings.save_window_mode(WindowMode::Borderless).unwrap();
+
+        let text = read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert!(text.contains("# keep this comment"));
+        assert!(text.contains("width = 1024 # and this one"));
+        let reloaded = Settings::parse(&text, "test", &[]).unwrap();
+        assert_eq!(reloaded.window.mode, WindowMode::Borderless);
+        assert_eq!(reloaded.window.width, 1024);
+    }
+
+    #[test]
+    fn bad_arguments_are_rejected() {
+        let args = ["--fullscreen".to_string()];
+        assert!(matches!(Settings::from_args(args.into_iter()), Err(SettingsError::Arguments(_))));
+
+        let args = ["--set".to_string()];
+        assert!(matches!(Settings::from_args(args.into_iter()), Err(SettingsError::Arguments(_))));
+    }
+}
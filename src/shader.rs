@@ -1,87 +1,456 @@
-use std::ffi::{CStr, CString};
-use std::fs::File;
-use std::io::Read;
-use cgmath::{Matrix, Matrix4};
-use gl::types::{GLchar, GLenum, GLint, GLuint};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::ffi::CString;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::time::SystemTime;
+use cgmath::{Matrix, Matrix3, Matrix4, Vector2, Vector3, Vector4};
+use gl::types::{GLbitfield, GLchar, GLenum, GLint, GLsizei, GLuint};
+use glutin_opengl_demo::supports_gl;
+use crate::error::Error;
+use crate::vertex::{ActiveAttribute, VertexLayout};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    Vertex,
+    Geometry,
+    Fragment,
+    /// Needs OpenGL 4.3, and can't be linked with any other stage.
+    Compute,
+}
+
+impl Stage {
+    fn gl_type(self) -> GLenum {
+        match self {
+            Stage::Vertex => gl::VERTEX_SHADER,
+            Stage::Geometry => gl::GEOMETRY_SHADER,
+            Stage::Fragment => gl::FRAGMENT_SHADER,
+            Stage::Compute => gl::COMPUTE_SHADER,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Stage::Vertex => "vertex",
+            Stage::Geometry => "geometry",
+            Stage::Fragment => "fragment",
+            Stage::Compute => "compute",
+        }
+    }
+}
+
+/// Collects the stages of a program, each from its own file, then builds them all at once.
+#[derive(Default)]
+pub struct ProgramBuilder {
+    stages : Vec<(Stage, PathBuf)>,
+    layout : Option<&'static VertexLayout>,
+    defines : Vec<(String, String)>,
+}
+
+impl ProgramBuilder {
+    pub fn vertex(self, path : &Path) -> Self {
+        self.stage(Stage::Vertex, path)
+    }
+
+    pub fn geometry(self, path : &Path) -> Self {
+        self.stage(Stage::Geometry, path)
+    }
+
+    pub fn fragment(self, path : &Path) -> Self {
+        self.stage(Stage::Fragment, path)
+    }
+
+    pub fn compute(self, path : &Path) -> Self {
+        self.stage(Stage::Compute, path)
+    }
+
+    fn stage(mut self, stage : Stage, path : &Path) -> Self {
+        self.stages.push((stage, path.to_path_buf()));
+        self
+    }
+
+    /// The vertices the program is drawn with, the vertex stage's inputs are checked against it.
+    pub fn layout(mut self, layout : &'static VertexLayout) -> Self {
+        self.layout = Some(layout);
+        self
+    }
+
+    /// Adds `#define name value` to every stage, for turning features on and off.
+    pub fn define(mut self, name : &str, value : &str) -> Self {
+        self.defines.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// Compiles and links the program, failing with the GLSL info log if any stage doesn't build,
+    /// if the stages can't go together, or if the vertex stage's inputs don't match the layout.
+    pub fn build(self) -> Result<Shader, Error> {
+        let mut files = Vec::new();
+        let id = build_program(&self.stages, self.layout, &self.defines, &mut files)?;
+
+        Ok(Shader {
+            id,
+            uniforms: unsafe { uniform_locations(id) },
+            missing: RefCell::new(HashSet::new()),
+            stages: self.stages,
+            defines: self.defines,
+            sources: SourceFiles::new(files),
+            layout: self.layout,
+        })
+    }
+}
 
 pub struct Shader {
     //program id
-    pub id: u32
+    pub id: u32,
+    // every active uniform's location, looked up once after linking
+    uniforms : HashMap<String, GLint>,
+    // names that were set but aren't in the program, so each is only reported once
+    missing : RefCell<HashSet<String>>,
+    // kept so the program can be rebuilt when the files change
+    stages : Vec<(Stage, PathBuf)>,
+    defines : Vec<(String, String)>,
+    sources : SourceFiles,
+    // the vertices this program is drawn with, checked whenever it's linked
+    layout : Option<&'static VertexLayout>,
 }
 
 impl Shader {
-    pub fn new(vertex_file_path : &str, fragment_file_path : &str) -> Shader {
+    pub fn builder() -> ProgramBuilder {
+        ProgramBuilder::default()
+    }
+
+    /// Rebuilds the program if any of its source files changed since it was last compiled.
+    /// Returns whether the program was replaced, uniforms have to be set again when it was.
+    /// If the new sources don't build the old program is kept and the error returned,
+    /// it isn't tried again until the files change again.
+    pub fn reload_if_changed(&mut self) -> Result<bool, Error> {
+        let rebuilt = self.sources.rebuild_if_changed(|files| {
+            build_program(&self.stages, self.layout, &self.defines, files)
+        })?;
+        let Some(id) = rebuilt else {
+            return Ok(false);
+        };
+
+        unsafe {
+            gl::DeleteProgram(self.id);
+            self.uniforms = uniform_locations(id);
+        }
+        self.id = id;
+        self.missing.borrow_mut().clear();
+
+        Ok(true)
+    }
 
-        let mut shader_program = Shader { id: 0};
+    /// Runs a compute program over `groups` work groups, then waits for the writes `barriers`
+    /// names (e.g. GL_SHADER_STORAGE_BARRIER_BIT) to be visible before anything reads them.
+    pub unsafe fn dispatch(&self, groups : [u32; 3], barriers : GLbitfield) {
+        gl::UseProgram(self.id);
+        gl::DispatchCompute(groups[0], groups[1], groups[2]);
+        gl::MemoryBarrier(barriers);
+    }
 
-        let vertex_shader = shader_code_from_file(vertex_file_path);
-        let fragment_shader = shader_code_from_file(fragment_file_path);
+    /// Sets a uniform in this program, which has to be the one in use.
+    /// Names of arrays set the whole array, `name[i]` sets from element i onwards.
+    pub unsafe fn set_uniform<U : Uniform + ?Sized>(&self, name : &str, value : &U) {
+        match self.uniforms.get(name) {
+            Some(&location) => value.set(location),
+            None => {
+                // GLSL compilers drop uniforms that don't affect the output, so this isn't always a typo
+                if self.missing.borrow_mut().insert(name.to_string()) {
+                    println!("{} has no uniform named '{}'", self.stages[0].1.display(), name);
+                }
+            }
+        }
+    }
+}
 
-        // Compile and link shaders
-        let vertex_shader = compile_shader(vertex_shader, gl::VERTEX_SHADER);
-        let fragment_shader = compile_shader(fragment_shader, gl::FRAGMENT_SHADER);
-        shader_program.id = create_shader_program(vertex_shader, fragment_shader);
+/// A value that can be stored in a uniform of the matching GLSL type,
+/// slices fill arrays of that type.
+pub trait Uniform {
+    /// Sets the uniform at `location` in the program that's in use.
+    unsafe fn set(&self, location : GLint);
+}
 
-        shader_program
+impl Uniform for bool {
+    unsafe fn set(&self, location : GLint) {
+        gl::Uniform1i(location, *self as GLint);
     }
+}
 
-    pub unsafe fn set_bool(&self, name: &CStr, value: bool) {
-        gl::Uniform1i(
-            gl::GetUniformLocation(self.id, name.as_ptr()),
-            value as i32
-        );
+impl Uniform for i32 {
+    unsafe fn set(&self, location : GLint) {
+        gl::Uniform1i(location, *self);
     }
-    pub unsafe fn set_int(&self, name: &CStr, value: i32) {
-        gl::Uniform1i(gl::GetUniformLocation(
-            self.id,
-            name.as_ptr()
-        ), value);
+}
+
+impl Uniform for u32 {
+    unsafe fn set(&self, location : GLint) {
+        gl::Uniform1ui(location, *self);
     }
-    pub unsafe fn set_float(&self, name: &CStr, value: f32) {
-        gl::Uniform1f(
-            gl::GetUniformLocation(self.id, name.as_ptr()),
-            value
-        );
+}
+
+impl Uniform for f32 {
+    unsafe fn set(&self, location : GLint) {
+        gl::Uniform1f(location, *self);
     }
+}
 
-    pub unsafe fn set_mat4(&self, name: &CStr, matrix : &Matrix4<f32>) {
-        let location = gl::GetUniformLocation(
-            self.id,
-            name.as_ptr()
-        );
+impl Uniform for Vector2<f32> {
+    unsafe fn set(&self, location : GLint) {
+        gl::Uniform2f(location, self.x, self.y);
+    }
+}
 
-        gl::UniformMatrix4fv(
-            location,
-            1,
-            gl::FALSE,
-            matrix.as_ptr()
-        );
+impl Uniform for Vector3<f32> {
+    unsafe fn set(&self, location : GLint) {
+        gl::Uniform3f(location, self.x, self.y, self.z);
     }
 }
 
-fn shader_code_from_file(file_path : &str) -> CString {
+impl Uniform for Vector4<f32> {
+    unsafe fn set(&self, location : GLint) {
+        gl::Uniform4f(location, self.x, self.y, self.z, self.w);
+    }
+}
 
-    let mut file = File::open(file_path)
-        .unwrap_or_else(|_| panic!("Failed to open {}", file_path));
+impl Uniform for Matrix3<f32> {
+    unsafe fn set(&self, location : GLint) {
+        gl::UniformMatrix3fv(location, 1, gl::FALSE, self.as_ptr());
+    }
+}
 
-    let mut code = String::new();
+impl Uniform for Matrix4<f32> {
+    unsafe fn set(&self, location : GLint) {
+        gl::UniformMatrix4fv(location, 1, gl::FALSE, self.as_ptr());
+    }
+}
 
-    file
-        .read_to_string(&mut code)
-        .expect("failed to read shader");
+// cgmath vectors and matrices are plain arrays of f32, so slices of them can be passed straight through
 
-    let code = CString::new(code.as_bytes()).unwrap();
+impl Uniform for [i32] {
+    unsafe fn set(&self, location : GLint) {
+        gl::Uniform1iv(location, self.len() as GLsizei, self.as_ptr());
+    }
+}
 
-    code
+impl Uniform for [f32] {
+    unsafe fn set(&self, location : GLint) {
+        gl::Uniform1fv(location, self.len() as GLsizei, self.as_ptr());
+    }
 }
 
-fn compile_shader(source: CString, shader_type: GLenum) -> GLuint {
+impl Uniform for [Vector2<f32>] {
+    unsafe fn set(&self, location : GLint) {
+        gl::Uniform2fv(location, self.len() as GLsizei, self.as_ptr() as *const f32);
+    }
+}
+
+impl Uniform for [Vector3<f32>] {
+    unsafe fn set(&self, location : GLint) {
+        gl::Uniform3fv(location, self.len() as GLsizei, self.as_ptr() as *const f32);
+    }
+}
+
+impl Uniform for [Vector4<f32>] {
+    unsafe fn set(&self, location : GLint) {
+        gl::Uniform4fv(location, self.len() as GLsizei, self.as_ptr() as *const f32);
+    }
+}
+
+impl Uniform for [Matrix3<f32>] {
+    unsafe fn set(&self, location : GLint) {
+        gl::UniformMatrix3fv(location, self.len() as GLsizei, gl::FALSE, self.as_ptr() as *const f32);
+    }
+}
+
+impl Uniform for [Matrix4<f32>] {
+    unsafe fn set(&self, location : GLint) {
+        gl::UniformMatrix4fv(location, self.len() as GLsizei, gl::FALSE, self.as_ptr() as *const f32);
+    }
+}
+
+impl Drop for Shader {
+    fn drop(&mut self) {
+        unsafe { gl::DeleteProgram(self.id); }
+    }
+}
+
+// every file a program was built from, includes too, and the newest modification time among them
+struct SourceFiles {
+    paths : Vec<PathBuf>,
+    modified : Option<SystemTime>,
+}
+
+impl SourceFiles {
+    fn new(paths : Vec<PathBuf>) -> Self {
+        SourceFiles {
+            modified: last_modified(&paths),
+            paths,
+        }
+    }
+
+    /// Runs `build` if any of the files changed since the last build, which gets to replace the
+    /// file list (the includes may have changed). A failed build is only retried after another change.
+    fn rebuild_if_changed<T>(&mut self,
+                             build : impl FnOnce(&mut Vec<PathBuf>) -> Result<T, Error>) -> Result<Option<T>, Error> {
+        if last_modified(&self.paths) == self.modified {
+            return Ok(None);
+        }
+
+        let built = build(&mut self.paths);
+        self.modified = last_modified(&self.paths);
+        built.map(Some)
+    }
+}
+
+// asks the linked program for every uniform it kept, rather than looking names up on every set
+unsafe fn uniform_locations(program : GLuint) -> HashMap<String, GLint> {
+    let mut count = 0;
+    gl::GetProgramiv(program, gl::ACTIVE_UNIFORMS, &mut count);
+    let mut max_length = 0;
+    gl::GetProgramiv(program, gl::ACTIVE_UNIFORM_MAX_LENGTH, &mut max_length);
+
+    let mut locations = HashMap::new();
+    for index in 0..count as GLuint {
+        let mut name = vec![0u8; max_length.max(1) as usize];
+        let (mut length, mut size, mut kind) = (0, 0, 0);
+        gl::GetActiveUniform(program, index, max_length, &mut length, &mut size, &mut kind, name.as_mut_ptr() as *mut GLchar);
+        let name = String::from_utf8_lossy(&name[..length as usize]).into_owned();
+
+        for name in uniform_names(&name, size) {
+            let location = gl::GetUniformLocation(program, CString::new(name.as_str()).unwrap().as_ptr());
+            // uniforms inside uniform blocks don't have a location
+            if location >= 0 {
+                locations.insert(name, location);
+            }
+        }
+    }
+
+    locations
+}
+
+// Arrays are reported as `name[0]` along with their length. They can be set whole through `name`
+// and each element has its own location, which the spec doesn't promise are consecutive.
+fn uniform_names(reported : &str, size : GLint) -> Vec<String> {
+    match reported.strip_suffix("[0]") {
+        Some(array) => std::iter::once(array.to_string())
+            .chain((0..size).map(|index| format!("{}[{}]", array, index)))
+            .collect(),
+        None => vec![reported.to_string()],
+    }
+}
+
+// `files` is set to every file the sources came from, as soon as they've been read
+fn build_program(stages : &[(Stage, PathBuf)],
+                 layout : Option<&VertexLayout>,
+                 defines : &[(String, String)],
+                 files : &mut Vec<PathBuf>) -> Result<GLuint, Error> {
+    let paths = || stages.iter().map(|(_, path)| path.clone()).collect::<Vec<_>>();
+    let kinds: Vec<Stage> = stages.iter().map(|&(stage, _)| stage).collect();
+    check_stages(&kinds).map_err(|message| Error::InvalidProgram { paths: paths(), message })?;
+
+    if kinds.contains(&Stage::Compute) && !supports_gl(4, 3) {
+        return Err(Error::Unsupported { feature: "compute shaders", version: "4.3" });
+    }
+
+    let sources = stages
+        .iter()
+        .map(|(_, path)| Preprocessed::load(path, defines))
+        .collect::<Result<Vec<_>, Error>>()?;
+    *files = Vec::new();
+    for file in sources.iter().flat_map(|source| &source.files) {
+        if !files.contains(file) {
+            files.push(file.clone());
+        }
+    }
+
+    // Compile and link shaders
+    let mut shaders = Vec::new();
+    for (stage, source) in kinds.iter().zip(&sources) {
+        match compile_shader(source, stage.gl_type()) {
+            Ok(shader) => shaders.push(shader),
+            Err(error) => {
+                unsafe { shaders.iter().for_each(|&shader| gl::DeleteShader(shader)); }
+                return Err(error);
+            }
+        }
+    }
+
+    let program = create_shader_program(&shaders, layout)
+        .map_err(|log| Error::ShaderLink { paths: paths(), log })?;
+
+    if let Some(layout) = layout {
+        unsafe {
+            if let Err(message) = layout.validate(&active_attributes(program)) {
+                gl::DeleteProgram(program);
+                return Err(Error::VertexLayout { paths: paths(), message });
+            }
+        }
+    }
+
+    Ok(program)
+}
+
+// which stages can be linked into one program
+fn check_stages(stages : &[Stage]) -> Result<(), String> {
+    for (index, stage) in stages.iter().enumerate() {
+        if stages[..index].contains(stage) {
+            return Err(format!("more than one {} stage", stage.name()));
+        }
+    }
+
+    if stages.contains(&Stage::Compute) {
+        if stages.len() > 1 {
+            return Err("a compute stage can't be linked with other stages".to_string());
+        }
+    } else if !stages.contains(&Stage::Vertex) {
+        return Err("drawing needs a vertex stage".to_string());
+    }
+
+    Ok(())
+}
+
+// the vertex shader inputs the linked program actually reads
+unsafe fn active_attributes(program : GLuint) -> Vec<ActiveAttribute> {
+    let mut count = 0;
+    gl::GetProgramiv(program, gl::ACTIVE_ATTRIBUTES, &mut count);
+    let mut max_length = 0;
+    gl::GetProgramiv(program, gl::ACTIVE_ATTRIBUTE_MAX_LENGTH, &mut max_length);
+
+    (0..count as GLuint)
+        .map(|index| {
+            let mut name = vec![0u8; max_length.max(1) as usize];
+            let (mut length, mut size, mut gl_type) = (0, 0, 0);
+            gl::GetActiveAttrib(program, index, max_length, &mut length, &mut size, &mut gl_type, name.as_mut_ptr() as *mut GLchar);
+            let name = String::from_utf8_lossy(&name[..length as usize]).into_owned();
+            let location = gl::GetAttribLocation(program, CString::new(name.as_str()).unwrap().as_ptr());
+
+            ActiveAttribute { name, location, gl_type }
+        })
+        .collect()
+}
+
+// None if none of the files can be read, which also counts as a change once they come back
+fn last_modified(paths : &[PathBuf]) -> Option<SystemTime> {
+    paths
+        .iter()
+        .filter_map(|path| fs::metadata(path).and_then(|metadata| metadata.modified()).ok())
+        .max()
+}
+
+fn compile_shader(source : &Preprocessed, shader_type : GLenum) -> Result<GLuint, Error> {
+    let code = CString::new(source.code.as_str()).map_err(|_| Error::ShaderCompile {
+        path: source.files[0].clone(),
+        log: "the source contains a NUL byte".to_string(),
+    })?;
+
     unsafe {
         // Create a new shader object
         let shader = gl::CreateShader(shader_type);
 
         // Set the shader source and compile it
-        gl::ShaderSource(shader, 1, &source.as_ptr(), std::ptr::null());
+        gl::ShaderSource(shader, 1, &code.as_ptr(), std::ptr::null());
         gl::CompileShader(shader);
 
         // Check for compilation errors
@@ -93,31 +462,45 @@ fn compile_shader(source: CString, shader_type: GLenum) -> GLuint {
             let mut log_length = 0;
             gl::GetShaderiv(shader, gl::INFO_LOG_LENGTH, &mut log_length);
 
-            let mut log = Vec::with_capacity(log_length as usize);
-            log.set_len(log_length as usize - 1); // Subtract 1 to ignore the null terminator
+            let mut log = vec![0u8; log_length.max(1) as usize];
 
             gl::GetShaderInfoLog(shader, log_length, std::ptr::null_mut(), log.as_mut_ptr() as *mut GLchar);
+            log.pop(); // ignore the null terminator
+            gl::DeleteShader(shader);
 
-            let error_message = String::from_utf8_lossy(&log);
-            println!("Shader compilation error: {}", error_message);
+            return Err(Error::ShaderCompile {
+                path: source.files[0].clone(),
+                log: annotate_info_log(source, &String::from_utf8_lossy(&log)),
+            });
         }
 
-        shader
+        Ok(shader)
     }
 }
 
-fn create_shader_program(vertex_shader: GLuint, fragment_shader: GLuint) -> GLuint {
+// the info log on failure
+fn create_shader_program(shaders : &[GLuint], layout : Option<&VertexLayout>) -> Result<GLuint, String> {
     unsafe {
         // Create a new shader program
         let shader_program = gl::CreateProgram();
 
-        // Attach the vertex and fragment shaders to the program
-        gl::AttachShader(shader_program, vertex_shader);
-        gl::AttachShader(shader_program, fragment_shader);
+        // Attach every stage to the program
+        for &shader in shaders {
+            gl::AttachShader(shader_program, shader);
+        }
+        if let Some(layout) = layout {
+            layout.bind_locations(shader_program);
+        }
 
         // Link the shader program
         gl::LinkProgram(shader_program);
 
+        // Detach and delete the individual shaders since they are now part of the program
+        for &shader in shaders {
+            gl::DetachShader(shader_program, shader);
+            gl::DeleteShader(shader);
+        }
+
         // Check for linking errors
         let mut success = gl::FALSE as GLint;
         gl::GetProgramiv(shader_program, gl::LINK_STATUS, &mut success);
@@ -127,21 +510,452 @@ fn create_shader_program(vertex_shader: GLuint, fragment_shader: GLuint) -> GLui
             let mut log_length = 0;
             gl::GetProgramiv(shader_program, gl::INFO_LOG_LENGTH, &mut log_length);
 
-            let mut log = Vec::with_capacity(log_length as usize);
-            log.set_len(log_length as usize - 1); // Subtract 1 to ignore the null terminator
+            let mut log = vec![0u8; log_length.max(1) as usize];
 
             gl::GetProgramInfoLog(shader_program, log_length, std::ptr::null_mut(), log.as_mut_ptr() as *mut GLchar);
+            log.pop(); // ignore the null terminator
+            gl::DeleteProgram(shader_program);
 
-            let error_message = String::from_utf8_lossy(&log);
-            println!("Shader program linking error: {}", error_message);
+            return Err(String::from_utf8_lossy(&log).into_owned());
         }
 
-        // Detach and delete the individual shaders since they are now part of the program
-        gl::DetachShader(shader_program, vertex_shader);
-        gl::DetachShader(shader_program, fragment_shader);
-        gl::DeleteShader(vertex_shader);
-        gl::DeleteShader(fragment_shader);
+        Ok(shader_program)
+    }
+}
+
+/// GLSL source with its `#include "file"`s expanded and feature `#define`s added after `#version`.
+/// Remembers which file and line every line came from, so compile errors can point at the original.
+pub struct Preprocessed {
+    pub code : String,
+    /// Every file that was read, the shader itself first.
+    pub files : Vec<PathBuf>,
+    // index into `files` and line number, for each line of `code`
+    origins : Vec<(usize, usize)>,
+}
+
+impl Preprocessed {
+    pub fn load(path : &Path, defines : &[(String, String)]) -> Result<Self, Error> {
+        Preprocessed::expand(path, defines, &mut |path| fs::read_to_string(path))
+    }
+
+    fn expand(path : &Path,
+              defines : &[(String, String)],
+              read : &mut dyn FnMut(&Path) -> io::Result<String>) -> Result<Self, Error> {
+        let mut files = Vec::new();
+        let mut lines = Vec::new();
+        include(&normalize(path), read, &mut Vec::new(), &mut files, &mut lines)?;
+
+        // nothing but comments can come before #version, so the defines go straight after it
+        let after_version = lines
+            .iter()
+            .position(|(text, file, _) : &(String, usize, usize)| *file == 0 && text.trim_start().starts_with("#version"))
+            .map_or(0, |index| index + 1);
+        let origin = if after_version == 0 { (0, 1) } else { (lines[after_version - 1].1, lines[after_version - 1].2) };
+        let defines = defines
+            .iter()
+            .map(|(name, value)| (format!("#define {} {}", name, value), origin.0, origin.1));
+        lines.splice(after_version..after_version, defines);
+
+        let mut code = String::new();
+        for (text, _, _) in &lines {
+            code += text;
+            code.push('\n');
+        }
+
+        Ok(Preprocessed {
+            code,
+            files,
+            origins: lines.into_iter().map(|(_, file, line)| (file, line)).collect(),
+        })
+    }
+
+    /// The file and line a line of `code` came from, both counting from 1.
+    fn origin(&self, line : usize) -> Option<(&Path, usize)> {
+        let &(file, line) = self.origins.get(line.checked_sub(1)?)?;
+        Some((&self.files[file], line))
+    }
+}
+
+// appends the lines of `path` to `lines` with includes expanded in place,
+// `stack` is the chain of files currently being included to catch cycles
+fn include(path : &Path,
+           read : &mut dyn FnMut(&Path) -> io::Result<String>,
+           stack : &mut Vec<PathBuf>,
+           files : &mut Vec<PathBuf>,
+           lines : &mut Vec<(String, usize, usize)>) -> Result<(), Error> {
+    let text = read(path).map_err(|error| Error::Io { path: path.to_path_buf(), error })?;
+
+    // the same file can be included more than once, e.g. by two different includes
+    let file = match files.iter().position(|file| file == path) {
+        Some(file) => file,
+        None => {
+            files.push(path.to_path_buf());
+            files.len() - 1
+        }
+    };
+    stack.push(path.to_path_buf());
+
+    for (index, text) in text.lines().enumerate() {
+        let Some(directive) = text.trim_start().strip_prefix("#include") else {
+            lines.push((text.to_string(), file, index + 1));
+            continue;
+        };
+        let error = |message : String| Error::ShaderPreprocess { path: path.to_path_buf(), line: index + 1, message };
+
+        let name = directive
+            .trim()
+            .strip_prefix('"')
+            .and_then(|name| name.strip_suffix('"'))
+            .ok_or_else(|| error("expected #include \"file\"".to_string()))?;
+        let included = normalize(&path.parent().unwrap_or(Path::new("")).join(name));
+
+        if stack.contains(&included) {
+            let chain: Vec<String> = stack.iter().chain([&included]).map(|file| file.display().to_string()).collect();
+            return Err(error(format!("include cycle: {}", chain.join(" -> "))));
+        }
+
+        include(&included, read, stack, files, lines)?;
+    }
+
+    stack.pop();
+    Ok(())
+}
+
+// drops `.` and resolves `..` without touching the file system, so a file reached through
+// different relative paths is still recognised
+fn normalize(path : &Path) -> PathBuf {
+    let mut normal = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir if matches!(normal.components().next_back(), Some(Component::Normal(_))) => {
+                normal.pop();
+            }
+            component => normal.push(component),
+        }
+    }
+
+    normal
+}
+
+// Rewrites a compile log so every message starts with the `file:line:` it's about, in the original
+// files rather than the expanded source, followed by that line. Drivers all format locations differently:
+//   Mesa    0:12(5): error: `foo' undeclared
+//   NVIDIA  0(12) : error C1008: undefined variable "foo"
+//   AMD     ERROR: 0:12: 'foo' : undeclared identifier
+// anything that doesn't match one of these is passed through untouched.
+fn annotate_info_log(source : &Preprocessed, log : &str) -> String {
+    let code_lines: Vec<&str> = source.code.lines().collect();
+    let mut annotated = String::new();
+
+    for message in log.lines().map(str::trim_end).filter(|message| !message.is_empty()) {
+        match find_location(message) {
+            Some((start, end, line)) => {
+                let before = message[..start].trim();
+                let after = message[end..].trim_start_matches([':', ' ']);
+                let text = if before.is_empty() { after.to_string() } else { format!("{} {}", before, after) };
+
+                match source.origin(line) {
+                    Some((file, original)) => {
+                        annotated += &format!("{}:{}: {}\n", file.display(), original, text);
+                        annotated += &format!("{:>5} | {}\n", original, code_lines[line - 1].trim());
+                    }
+                    // past the end of the source, there's no line to show
+                    None => annotated += &format!("{}:{}: {}\n", source.files[0].display(), line, text),
+                }
+            }
+            None => annotated += &format!("{}\n", message),
+        }
+    }
+
+    annotated
+}
+
+// finds `<string>:<line>`, `<string>:<line>(<column>)` or `<string>(<line>)` in a log message,
+// returning where it starts and ends and the line number
+fn find_location(message : &str) -> Option<(usize, usize, usize)> {
+    let bytes = message.as_bytes();
+    let digits_from = |from : usize| bytes[from..].iter().take_while(|byte| byte.is_ascii_digit()).count();
+
+    for start in 0..bytes.len() {
+        // the source string index has to be a number on its own, not the end of a word like C1008
+        if !bytes[start].is_ascii_digit() || (start > 0 && bytes[start - 1].is_ascii_alphanumeric()) {
+            continue;
+        }
+
+        let mut end = start + digits_from(start);
+        let separator = bytes.get(end).copied();
+        end += 1;
+
+        let line_length = if end <= bytes.len() { digits_from(end) } else { 0 };
+        if line_length == 0 {
+            continue;
+        }
+        let Ok(line) = message[end..end + line_length].parse() else {
+            continue;
+        };
+        end += line_length;
+
+        match separator {
+            Some(b'(') if bytes.get(end) == Some(&b')') => return Some((start, end + 1, line)),
+            Some(b':') => {
+                // Mesa adds the column in brackets
+                if bytes.get(end) == Some(&b'(') {
+                    let column_length = digits_from(end + 1);
+                    if column_length > 0 && bytes.get(end + 1 + column_length) == Some(&b')') {
+                        end += column_length + 2;
+                    }
+                }
+                return Some((start, end, line));
+            }
+            _ => continue,
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE : &str = "#version 330 core\nvoid main() {\n    gl_Position = foo;\n}\n";
+
+    // expands `path` from in-memory files
+    fn expand(files : &[(&str, &str)], path : &str, defines : &[(&str, &str)]) -> Result<Preprocessed, Error> {
+        let defines: Vec<(String, String)> = defines.iter().map(|&(n, v)| (n.to_string(), v.to_string())).collect();
+        let mut read = |path : &Path| {
+            files
+                .iter()
+                .find(|(name, _)| Path::new(name) == path)
+                .map(|(_, text)| text.to_string())
+                .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no such file"))
+        };
+
+        Preprocessed::expand(Path::new(path), &defines, &mut read)
+    }
 
-        shader_program
+    fn annotate(log : &str) -> String {
+        annotate_info_log(&expand(&[("shaders/test.vs", SOURCE)], "shaders/test.vs", &[]).unwrap(), log)
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn mesa_locations_point_at_the_source_line() {
+        assert_eq!(
+            annotate("0:3(19): error: `foo' undeclared\n"),
+            "shaders/test.vs:3: error: `foo' undeclared\n    3 | gl_Position = foo;\n"
+        );
+    }
+
+    #[test]
+    fn nvidia_and_amd_locations_are_understood() {
+        assert_eq!(
+            annotate("0(3) : error C1008: undefined variable \"foo\""),
+            "shaders/test.vs:3: error C1008: undefined variable \"foo\"\n    3 | gl_Position = foo;\n"
+        );
+        assert_eq!(
+            annotate("ERROR: 0:3: 'foo' : undeclared identifier"),
+            "shaders/test.vs:3: ERROR: 'foo' : undeclared identifier\n    3 | gl_Position = foo;\n"
+        );
+    }
+
+    #[test]
+    fn messages_without_a_location_are_kept() {
+        assert_eq!(annotate("error: linking failed\n\n"), "error: linking failed\n");
+        // a line past the end of the file still gets its location, just no source
+        assert_eq!(annotate("0:40(1): error: syntax error"), "shaders/test.vs:40: error: syntax error\n");
+    }
+
+    #[test]
+    fn includes_are_expanded_relative_to_the_including_file() {
+        let files = [
+            ("shaders/main.fs", "#version 330 core\n#include \"include/light.glsl\"\nvoid main() {}"),
+            ("shaders/include/light.glsl", "#include \"../common.glsl\"\nfloat light;"),
+            ("shaders/common.glsl", "float common;"),
+        ];
+        let source = expand(&files, "shaders/main.fs", &[]).unwrap();
+
+        assert_eq!(source.code, "#version 330 core\nfloat common;\nfloat light;\nvoid main() {}\n");
+        assert_eq!(source.files, [
+            PathBuf::from("shaders/main.fs"),
+            PathBuf::from("shaders/include/light.glsl"),
+            PathBuf::from("shaders/common.glsl"),
+        ]);
+    }
+
+    #[test]
+    fn defines_go_after_the_version() {
+        let files = [("a.fs", "// comment\n#version 330 core\nvoid main() {}")];
+        let source = expand(&files, "a.fs", &[("FOG", "1"), ("LIGHTS", "4")]).unwrap();
+
+        assert_eq!(source.code, "// comment\n#version 330 core\n#define FOG 1\n#define LIGHTS 4\nvoid main() {}\n");
+    }
+
+    #[test]
+    fn errors_in_includes_point_at_the_included_file() {
+        let files = [
+            ("shaders/main.fs", "#version 330 core\n#include \"fog.glsl\"\nvoid main() {}"),
+            ("shaders/fog.glsl", "// fog\nfloat fog = bad;"),
+        ];
+        let source = expand(&files, "shaders/main.fs", &[("FOG", "1")]).unwrap();
+
+        // the expanded line 4 is line 2 of the include, after the version and the define
+        assert_eq!(
+            annotate_info_log(&source, "0:4(13): error: `bad' undeclared"),
+            "shaders/fog.glsl:2: error: `bad' undeclared\n    2 | float fog = bad;\n"
+        );
+        // the define is blamed on the #version line it was added after
+        assert_eq!(source.origin(2).unwrap(), (Path::new("shaders/main.fs"), 1));
+    }
+
+    #[test]
+    fn include_cycles_are_reported() {
+        let files = [
+            ("a.glsl", "#include \"b.glsl\""),
+            ("b.glsl", "\n#include \"./a.glsl\""),
+        ];
+
+        match expand(&files, "a.glsl", &[]) {
+            Err(Error::ShaderPreprocess { path, line, message }) => {
+                assert_eq!((path, line), (PathBuf::from("b.glsl"), 2));
+                assert_eq!(message, "include cycle: a.glsl -> b.glsl -> a.glsl");
+            }
+            other => panic!("expected a cycle, got {:?}", other.map(|source| source.code)),
+        }
+    }
+
+    #[test]
+    fn bad_includes_fail() {
+        let files = [("a.glsl", "#include <b.glsl>"), ("c.glsl", "#include \"missing.glsl\"")];
+
+        assert!(matches!(expand(&files, "a.glsl", &[]), Err(Error::ShaderPreprocess { line: 1, .. })));
+        assert!(matches!(expand(&files, "c.glsl", &[]), Err(Error::Io { .. })));
+    }
+
+    #[test]
+    fn shipped_shaders_preprocess() {
+        for path in [
+            "shaders/shader.vs", "shaders/shader.fs", "shaders/line.vs", "shaders/line.fs", "shaders/normals.vs", "shaders/normals.gs"
+        ] {
+            let source = Preprocessed::load(Path::new(path), &[("DEBUG_VIEWS".to_string(), "1".to_string())]).unwrap();
+            assert!(!source.code.contains("#include"), "{}", path);
+        }
+    }
+
+    #[test]
+    fn stages_have_to_fit_together() {
+        use Stage::*;
+
+        assert_eq!(check_stages(&[Vertex, Fragment]), Ok(()));
+        assert_eq!(check_stages(&[Vertex, Geometry, Fragment]), Ok(()));
+        assert_eq!(check_stages(&[Compute]), Ok(()));
+
+        assert!(check_stages(&[]).is_err());
+        assert!(check_stages(&[Fragment]).unwrap_err().contains("vertex"));
+        assert!(check_stages(&[Vertex, Fragment, Fragment]).unwrap_err().contains("more than one fragment"));
+        assert!(check_stages(&[Compute, Fragment]).unwrap_err().contains("compute"));
+    }
+
+    #[test]
+    fn arrays_can_be_set_whole_or_by_element() {
+        assert_eq!(uniform_names("model", 1), ["model"]);
+        assert_eq!(uniform_names("lights[0]", 3), ["lights", "lights[0]", "lights[1]", "lights[2]"]);
+        // only the trailing index marks an array of plain values
+        assert_eq!(uniform_names("lights[0].colour", 1), ["lights[0].colour"]);
+    }
+
+    // a fresh directory per test so tests can run in parallel
+    fn temp_directory(name : &str) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!("glutin_demo_shader_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        directory
+    }
+
+    // creates the file if needed and sets its modification time, seconds after the epoch
+    fn touch(path : &Path, seconds : u64) {
+        let file = fs::File::options().write(true).create(true).truncate(false).open(path).unwrap();
+        file.set_modified(SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(seconds)).unwrap();
+    }
+
+    fn failed_build() -> Error {
+        Error::ShaderCompile { path: PathBuf::from("test.vs"), log: "broken".to_string() }
+    }
+
+    #[test]
+    fn last_modified_is_the_newest_file_including_includes() {
+        let directory = temp_directory("last_modified");
+        let shader = directory.join("test.vs");
+        let include = directory.join("common.glsl");
+        touch(&shader, 100);
+        touch(&include, 50);
+        let files = vec![shader.clone(), include.clone(), directory.join("missing.glsl")];
+
+        let before = last_modified(&files);
+        assert_eq!(before, Some(SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(100)));
+
+        // editing only the include is a change
+        touch(&include, 200);
+        assert_ne!(last_modified(&files), before);
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn programs_are_only_rebuilt_after_a_change() {
+        let directory = temp_directory("rebuild");
+        let shader = directory.join("test.vs");
+        touch(&shader, 100);
+        let mut sources = SourceFiles::new(vec![shader.clone()]);
+
+        assert_eq!(sources.rebuild_if_changed(|_| -> Result<(), Error> { panic!("nothing changed") }).unwrap(), None);
+
+        touch(&shader, 200);
+        assert_eq!(sources.rebuild_if_changed(|_| Ok(7)).unwrap(), Some(7));
+        assert_eq!(sources.rebuild_if_changed(|_| Ok(8)).unwrap(), None);
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn failed_rebuilds_wait_for_the_next_change() {
+        let directory = temp_directory("failed_rebuild");
+        let shader = directory.join("test.vs");
+        touch(&shader, 100);
+        let mut sources = SourceFiles::new(vec![shader.clone()]);
+
+        touch(&shader, 200);
+        assert!(sources.rebuild_if_changed(|_| -> Result<(), Error> { Err(failed_build()) }).is_err());
+
+        // the broken sources aren't compiled again every frame
+        assert_eq!(sources.rebuild_if_changed(|_| -> Result<(), Error> { panic!("retried") }).unwrap(), None);
+
+        touch(&shader, 300);
+        assert_eq!(sources.rebuild_if_changed(|_| Ok(1)).unwrap(), Some(1));
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn newly_included_files_are_watched() {
+        let directory = temp_directory("new_include");
+        let shader = directory.join("test.vs");
+        let include = directory.join("common.glsl");
+        touch(&shader, 100);
+        touch(&include, 100);
+        let mut sources = SourceFiles::new(vec![shader.clone()]);
+
+        // the edit adds an #include
+        touch(&shader, 200);
+        let rebuilt = sources.rebuild_if_changed(|files| {
+            files.push(include.clone());
+            Ok(())
+        });
+        assert_eq!(rebuilt.unwrap(), Some(()));
+
+        touch(&include, 300);
+        assert_eq!(sources.rebuild_if_changed(|_| Ok(())).unwrap(), Some(()));
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
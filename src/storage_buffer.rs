@@ -0,0 +1,64 @@
+use std::mem;
+use gl::types::{GLsizeiptr, GLuint};
+use glutin_opengl_demo::supports_gl;
+use crate::error::Error;
+
+/// A shader storage buffer (GL 4.3), read and written by shaders through
+/// `layout (std430, binding = n) buffer` blocks.
+pub struct StorageBuffer {
+    pub id : GLuint,
+    // in bytes
+    size : usize,
+}
+
+impl StorageBuffer {
+    /// Uploads `data` into a new buffer, which keeps its size from then on.
+    pub unsafe fn new<T>(data : &[T]) -> Result<Self, Error> {
+        if !supports_gl(4, 3) {
+            return Err(Error::Unsupported { feature: "shader storage buffers", version: "4.3" });
+        }
+
+        let mut id = 0;
+        gl::GenBuffers(1, &mut id);
+        gl::BindBuffer(gl::SHADER_STORAGE_BUFFER, id);
+        // written by shaders and read back by them, the CPU only fills it
+        gl::BufferData(
+            gl::SHADER_STORAGE_BUFFER,
+            mem::size_of_val(data) as GLsizeiptr,
+            data.as_ptr() as *const std::ffi::c_void,
+            gl::DYNAMIC_COPY
+        );
+        gl::BindBuffer(gl::SHADER_STORAGE_BUFFER, 0);
+
+        Ok(StorageBuffer {
+            id,
+            size: mem::size_of_val(data),
+        })
+    }
+
+    /// Overwrites part of the buffer, `offset` counts elements of `T`.
+    pub unsafe fn write<T>(&self, offset : usize, data : &[T]) {
+        let start = offset * mem::size_of::<T>();
+        assert!(start + mem::size_of_val(data) <= self.size, "write past the end of a storage buffer");
+
+        gl::BindBuffer(gl::SHADER_STORAGE_BUFFER, self.id);
+        gl::BufferSubData(
+            gl::SHADER_STORAGE_BUFFER,
+            start as isize,
+            mem::size_of_val(data) as GLsizeiptr,
+            data.as_ptr() as *const std::ffi::c_void
+        );
+        gl::BindBuffer(gl::SHADER_STORAGE_BUFFER, 0);
+    }
+
+    /// Makes the buffer the one shaders see at `binding`.
+    pub unsafe fn bind(&self, binding : GLuint) {
+        gl::BindBufferBase(gl::SHADER_STORAGE_BUFFER, binding, self.id);
+    }
+}
+
+impl Drop for StorageBuffer {
+    fn drop(&mut self) {
+        unsafe { gl::DeleteBuffers(1, &self.id); }
+    }
+}
@@ -0,0 +1,364 @@
+use crate::block::{BlockId, AIR, BRICK, CRATE, SMILEY, WOOD};
+use crate::chunk::{Chunk, ChunkPos, CHUNK_SIZE};
+
+// terrain shape
+const BASE_HEIGHT : f64 = 8.0;
+const HEIGHT_VARIATION : f64 = 20.0;
+const HEIGHT_FREQUENCY : f64 = 1.0 / 96.0;
+const HEIGHT_OCTAVES : u32 = 5;
+const DIRT_DEPTH : i32 = 3;
+
+// caves are carved wherever the cave noise rises above the threshold
+const CAVE_FREQUENCY : f64 = 1.0 / 24.0;
+const CAVE_OCTAVES : u32 = 2;
+const CAVE_THRESHOLD : f64 = 0.3;
+// keep caves from breaking through the surface layer
+const CAVE_ROOF : i32 = 4;
+
+// size of the floor in the demo world
+const DEMO_FLOOR_SIZE : i32 = 64;
+
+/// How the world is filled, chosen in the settings file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WorldType {
+    /// The hand-built test scene: a floor and a few loose blocks.
+    Demo,
+    /// Noise generated hills and caves, the same seed always gives the same world.
+    Terrain { seed : u64 },
+}
+
+pub enum WorldGenerator {
+    Demo,
+    Terrain(Box<TerrainGenerator>),
+}
+
+impl WorldGenerator {
+    pub fn new(world_type : WorldType) -> Self {
+        match world_type {
+            WorldType::Demo => WorldGenerator::Demo,
+            WorldType::Terrain { seed } => WorldGenerator::Terrain(Box::new(TerrainGenerator::new(seed))),
+        }
+    }
+
+    pub fn generate_chunk(&self, pos : ChunkPos) -> Chunk {
+        match self {
+            WorldGenerator::Demo => fill_chunk(pos, demo_block),
+            WorldGenerator::Terrain(terrain) => terrain.generate_chunk(pos),
+        }
+    }
+
+    /// Range of chunk y coordinates that can contain blocks.
+    pub fn chunk_y_range(&self) -> std::ops::Range<i32> {
+        match self {
+            WorldGenerator::Demo => -1..1,
+            WorldGenerator::Terrain(_) => {
+                let size = CHUNK_SIZE as f64;
+                let lowest = ((BASE_HEIGHT - HEIGHT_VARIATION) / size).floor() as i32 - 1;
+                let highest = ((BASE_HEIGHT + HEIGHT_VARIATION) / size).ceil() as i32;
+                lowest..highest + 1
+            }
+        }
+    }
+
+    /// Height of the ground at the given column, used to place the camera.
+    pub fn surface_height(&self, x : i32, z : i32) -> i32 {
+        match self {
+            WorldGenerator::Demo => -3,
+            WorldGenerator::Terrain(terrain) => terrain.surface_height(x, z),
+        }
+    }
+}
+
+fn demo_block(x : i32, y : i32, z : i32) -> BlockId {
+    let half = DEMO_FLOOR_SIZE / 2;
+
+    match (x, y, z) {
+        // checkered floor spanning several chunks
+        (x, -3, z) if (-half..half).contains(&x) && (-half..half).contains(&z) => {
+            if (x + z) & 1 == 0 { BRICK } else { WOOD }
+        }
+        (0, 0, 0) | (0, -1, -4) => BRICK,
+        (2, 0, -1) => WOOD,
+        (-2, 1, -3) => SMILEY,
+        _ => AIR,
+    }
+}
+
+// build a chunk by asking for every block by its world position
+fn fill_chunk<F : Fn(i32, i32, i32) -> BlockId>(pos : ChunkPos, block_at : F) -> Chunk {
+    let mut chunk = Chunk::default();
+    let size = CHUNK_SIZE as i32;
+
+    for y in 0..CHUNK_SIZE {
+        for z in 0..CHUNK_SIZE {
+            for x in 0..CHUNK_SIZE {
+                let block = block_at(
+                    pos.x * size + x as i32,
+                    pos.y * size + y as i32,
+                    pos.z * size + z as i32,
+                );
+                chunk.set(x, y, z, block);
+            }
+        }
+    }
+
+    chunk
+}
+
+/// Height map plus cave generation from layered Perlin noise.
+pub struct TerrainGenerator {
+    height_noise : Perlin,
+    cave_noise : Perlin,
+}
+
+impl TerrainGenerator {
+    pub fn new(seed : u64) -> Self {
+        TerrainGenerator {
+            height_noise: Perlin::new(seed),
+            // derive a second, unrelated seed so caves don't follow the hills
+            cave_noise: Perlin::new(seed ^ 0x9E37_79B9_7F4A_7C15),
+        }
+    }
+
+    pub fn surface_height(&self, x : i32, z : i32) -> i32 {
+        let noise = self.height_noise.fbm2(
+            x as f64 * HEIGHT_FREQUENCY,
+            z as f64 * HEIGHT_FREQUENCY,
+            HEIGHT_OCTAVES
+        );
+
+        (BASE_HEIGHT + noise * HEIGHT_VARIATION).floor() as i32
+    }
+
+    pub fn generate_chunk(&self, pos : ChunkPos) -> Chunk {
+        let mut chunk = Chunk::default();
+        let size = CHUNK_SIZE as i32;
+
+        for z in 0..CHUNK_SIZE {
+            for x in 0..CHUNK_SIZE {
+                let world_x = pos.x * size + x as i32;
+                let world_z = pos.z * size + z as i32;
+                let surface = self.surface_height(world_x, world_z);
+
+                for y in 0..CHUNK_SIZE {
+                    let world_y = pos.y * size + y as i32;
+                    if world_y > surface {
+                        break;
+                    }
+
+                    if world_y < surface - CAVE_ROOF && self.is_cave(world_x, world_y, world_z) {
+                        continue;
+                    }
+
+                    let block = if world_y == surface {
+                        CRATE
+                    } else if world_y > surface - DIRT_DEPTH {
+                        WOOD
+                    } else {
+                        BRICK
+                    };
+                    chunk.set(x, y, z, block);
+                }
+            }
+        }
+
+        chunk
+    }
+
+    fn is_cave(&self, x : i32, y : i32, z : i32) -> bool {
+        let noise = self.cave_noise.fbm3(
+            x as f64 * CAVE_FREQUENCY,
+            y as f64 * CAVE_FREQUENCY,
+            z as f64 * CAVE_FREQUENCY,
+            CAVE_OCTAVES
+        );
+
+        noise > CAVE_THRESHOLD
+    }
+}
+
+/// Seeded gradient noise (Ken Perlin's improved noise), output roughly in -1..1.
+pub struct Perlin {
+    permutation : [u8; 512],
+}
+
+impl Perlin {
+    pub fn new(seed : u64) -> Self {
+        let mut table = [0u8; 256];
+        for (i, value) in table.iter_mut().enumerate() {
+            *value = i as u8;
+        }
+
+        // Fisher-Yates shuffle driven by the seed
+        let mut state = seed;
+        for i in (1..256).rev() {
+            let j = (split_mix(&mut state) % (i as u64 + 1)) as usize;
+            table.swap(i, j);
+        }
+
+        let mut permutation = [0u8; 512];
+        for i in 0..512 {
+            permutation[i] = table[i & 255];
+        }
+
+        Perlin { permutation }
+    }
+
+    pub fn noise2(&self, x : f64, y : f64) -> f64 {
+        self.noise3(x, y, 0.0)
+    }
+
+    pub fn noise3(&self, x : f64, y : f64, z : f64) -> f64 {
+        let p = &self.permutation;
+
+        // unit cube containing the point, and the point's position inside it
+        let xi = x.floor() as i64 as usize & 255;
+        let yi = y.floor() as i64 as usize & 255;
+        let zi = z.floor() as i64 as usize & 255;
+        let x = x - x.floor();
+        let y = y - y.floor();
+        let z = z - z.floor();
+
+        let u = fade(x);
+        let v = fade(y);
+        let w = fade(z);
+
+        // hash the eight cube corners
+        let a = p[xi] as usize + yi;
+        let aa = p[a] as usize + zi;
+        let ab = p[a + 1] as usize + zi;
+        let b = p[xi + 1] as usize + yi;
+        let ba = p[b] as usize + zi;
+        let bb = p[b + 1] as usize + zi;
+
+        lerp(w,
+             lerp(v,
+                  lerp(u, grad(p[aa], x, y, z), grad(p[ba], x - 1.0, y, z)),
+                  lerp(u, grad(p[ab], x, y - 1.0, z), grad(p[bb], x - 1.0, y - 1.0, z))),
+             lerp(v,
+                  lerp(u, grad(p[aa + 1], x, y, z - 1.0), grad(p[ba + 1], x - 1.0, y, z - 1.0)),
+                  lerp(u, grad(p[ab + 1], x, y - 1.0, z - 1.0), grad(p[bb + 1], x - 1.0, y - 1.0, z - 1.0))))
+    }
+
+    /// Several octaves of 2D noise, each at double the frequency and half the amplitude.
+    pub fn fbm2(&self, x : f64, y : f64, octaves : u32) -> f64 {
+        self.fbm(octaves, |frequency| self.noise2(x * frequency, y * frequency))
+    }
+
+    pub fn fbm3(&self, x : f64, y : f64, z : f64, octaves : u32) -> f64 {
+        self.fbm(octaves, |frequency| self.noise3(x * frequency, y * frequency, z * frequency))
+    }
+
+    fn fbm<F : Fn(f64) -> f64>(&self, octaves : u32, sample : F) -> f64 {
+        let mut total = 0.0;
+        let mut amplitude = 1.0;
+        let mut frequency = 1.0;
+        let mut max_amplitude = 0.0;
+
+        for _ in 0..octaves {
+            total += sample(frequency) * amplitude;
+            max_amplitude += amplitude;
+            amplitude *= 0.5;
+            frequency *= 2.0;
+        }
+
+        // keep the result in the same range as a single octave
+        total / max_amplitude
+    }
+}
+
+fn split_mix(state : &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+fn fade(t : f64) -> f64 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(t : f64, a : f64, b : f64) -> f64 {
+    a + t * (b - a)
+}
+
+// dot product with one of 12 gradient directions picked by the hash
+fn grad(hash : u8, x : f64, y : f64, z : f64) -> f64 {
+    let h = hash & 15;
+    let u = if h < 8 { x } else { y };
+    let v = if h < 4 {
+        y
+    } else if h == 12 || h == 14 {
+        x
+    } else {
+        z
+    };
+
+    (if h & 1 == 0 { u } else { -u }) + (if h & 2 == 0 { v } else { -v })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::is_solid;
+
+    fn chunks_equal(a : &Chunk, b : &Chunk) -> bool {
+        (0..CHUNK_SIZE).all(|x| (0..CHUNK_SIZE).all(|y| (0..CHUNK_SIZE).all(|z| a.get(x, y, z) == b.get(x, y, z))))
+    }
+
+    #[test]
+    fn same_seed_same_chunks() {
+        let first = TerrainGenerator::new(42);
+        let second = TerrainGenerator::new(42);
+
+        for pos in [ChunkPos::new(0, 0, 0), ChunkPos::new(-3, -1, 7), ChunkPos::new(12, 1, -5)] {
+            assert!(chunks_equal(&first.generate_chunk(pos), &second.generate_chunk(pos)));
+        }
+    }
+
+    #[test]
+    fn different_seeds_different_terrain() {
+        let first = TerrainGenerator::new(1);
+        let second = TerrainGenerator::new(2);
+
+        let differs = (0..64).any(|x| first.surface_height(x * 7, x * 3) != second.surface_height(x * 7, x * 3));
+        assert!(differs);
+    }
+
+    #[test]
+    fn noise_stays_in_range() {
+        let perlin = Perlin::new(7);
+
+        for i in 0..1000 {
+            let t = i as f64 * 0.173;
+            let value = perlin.noise3(t, t * 0.5 - 3.0, -t * 1.3);
+            assert!((-1.1..=1.1).contains(&value));
+        }
+    }
+
+    #[test]
+    fn noise_is_zero_on_lattice_points() {
+        let perlin = Perlin::new(7);
+
+        assert_eq!(perlin.noise3(3.0, -2.0, 5.0), 0.0);
+    }
+
+    #[test]
+    fn columns_are_solid_up_to_the_surface() {
+        let generator = WorldGenerator::new(WorldType::Terrain { seed: 99 });
+        let size = CHUNK_SIZE as i32;
+
+        for (x, z) in [(0, 0), (5, 11), (-20, 33)] {
+            let surface = generator.surface_height(x, z);
+            let block_at = |y : i32| {
+                let (pos, (lx, ly, lz)) = ChunkPos::of_block(x, y, z);
+                generator.generate_chunk(pos).get(lx, ly, lz)
+            };
+
+            assert!(is_solid(block_at(surface)));
+            assert_eq!(block_at(surface + 1), AIR);
+            assert!(generator.chunk_y_range().contains(&surface.div_euclid(size)));
+        }
+    }
+}
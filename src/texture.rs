@@ -1,54 +1,53 @@
-use std::path::Path;
-use gl::types::{GLenum, GLuint};
-use image::{DynamicImage, GenericImage, load};
+use gl::types::{GLenum, GLsizei, GLuint};
+use image::RgbaImage;
+use crate::error::Error;
 
 pub struct Texture {
-    pub id : GLuint
+    pub id : GLuint,
+    pub target : GLenum,
 }
 
 impl Texture {
-    pub unsafe fn new(image_path : &str) -> Self {
-        let image = image::open(
-            &Path::new(image_path))
-            .expect("Failed to load texture"
-            );
-
-        let id = load_texture(image, gl::RGB, false);
-
-        Texture {
-            id
+    /// Uploads an image as a GL_TEXTURE_2D, failing if it's too big for the GPU.
+    pub unsafe fn from_image(image : &RgbaImage) -> Result<Self, Error> {
+        // GL would leave the texture empty instead of failing, so check up front
+        let mut max_size = 0;
+        gl::GetIntegerv(gl::MAX_TEXTURE_SIZE, &mut max_size);
+        let max = max_size as u32;
+        if image.width() > max || image.height() > max {
+            return Err(Error::TextureTooLarge { width: image.width(), height: image.height(), max });
         }
+
+        let mut texture = 0;
+
+        gl::GenTextures(1, &mut texture);
+        gl::BindTexture(gl::TEXTURE_2D, texture);
+        // the atlas is sampled by region, never wrapped
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+        // set texture filtering parameters, no mipmaps since smaller levels would blend neighbouring regions
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
+
+        gl::TexImage2D(gl::TEXTURE_2D,
+                       0,
+                       gl::RGBA as i32,
+                       image.width() as GLsizei,
+                       image.height() as GLsizei,
+                       0,
+                       gl::RGBA,
+                       gl::UNSIGNED_BYTE,
+                       image.as_ptr() as *const std::ffi::c_void);
+
+        Ok(Texture {
+            id: texture,
+            target: gl::TEXTURE_2D,
+        })
     }
 }
-unsafe fn load_texture(mut img: image::DynamicImage, format : GLenum, flip : bool) -> GLuint {
-    //borrowed directly from : https://github.com/bwasty/learn-opengl-rs/blob/master/src/_1_getting_started/_4_1_textures.rs
-    let mut texture = 0;
-
-    gl::GenTextures(1, &mut texture);
-    gl::BindTexture(gl::TEXTURE_2D, texture); // all upcoming GL_TEXTURE_2D operations now have effect on this texture object
-    // set the texture wrapping parameters
-    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::REPEAT as i32); // set texture wrapping to gl::REPEAT (default wrapping method)
-    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::REPEAT as i32);
-    // set texture filtering parameters
-    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32);
-    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
 
-    // load image, create texture and generate mipmaps
-    if flip {
-        img = img.flipv(); // flip loaded texture on the y-axis.
+impl Drop for Texture {
+    fn drop(&mut self) {
+        unsafe { gl::DeleteTextures(1, &self.id); }
     }
-
-    let data = img.raw_pixels();
-    gl::TexImage2D(gl::TEXTURE_2D,
-                   0,
-                   gl::RGB as i32,
-                   img.width() as i32,
-                   img.height() as i32,
-                   0,
-                   format,
-                   gl::UNSIGNED_BYTE,
-                   &data[0] as *const u8 as *const std::ffi::c_void);
-    gl::GenerateMipmap(gl::TEXTURE_2D);
-
-    texture
-}
\ No newline at end of file
+}
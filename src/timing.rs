@@ -0,0 +1,164 @@
+use std::time::{Duration, Instant};
+
+// after a long stall (dragging the window, a breakpoint) the simulation skips ahead
+// rather than trying to catch up on every missed step at once
+const MAX_STEPS_PER_FRAME : u32 = 5;
+
+// with nothing else holding frames back, drawing as fast as possible would only burn a core
+const UNSYNCED_MAX_FPS : u32 = 500;
+
+/// Splits variable frame times into fixed simulation steps, so the simulation behaves the same
+/// at any frame rate. Time left over between steps is carried into the next frame.
+pub struct FixedTimestep {
+    step : Duration,
+    accumulator : Duration,
+}
+
+impl FixedTimestep {
+    pub fn new(steps_per_second : u32) -> Self {
+        FixedTimestep {
+            step: Duration::from_secs(1) / steps_per_second.max(1),
+            accumulator: Duration::ZERO,
+        }
+    }
+
+    pub fn step_seconds(&self) -> f32 {
+        self.step.as_secs_f32()
+    }
+
+    /// Adds the time a frame took and returns how many steps to simulate for it.
+    pub fn advance(&mut self, elapsed : Duration) -> u32 {
+        self.accumulator += elapsed;
+
+        let mut steps = 0;
+        while self.accumulator >= self.step {
+            self.accumulator -= self.step;
+            steps += 1;
+
+            if steps == MAX_STEPS_PER_FRAME {
+                self.accumulator = Duration::ZERO;
+                break;
+            }
+        }
+
+        steps
+    }
+
+    /// How far the current frame is between the last step and the next one, from 0 to 1,
+    /// used to interpolate what gets drawn.
+    pub fn alpha(&self) -> f32 {
+        self.accumulator.as_secs_f32() / self.step.as_secs_f32()
+    }
+}
+
+/// Caps the frame rate by telling the event loop to sleep until the next frame is due.
+pub struct FrameLimiter {
+    // None when vsync paces the frames
+    interval : Option<Duration>,
+    next_frame : Instant,
+}
+
+impl FrameLimiter {
+    /// A `max_fps` of 0 leaves the frame rate to vsync, where swapping buffers blocks until the
+    /// next refresh. Without vsync it falls back to `UNSYNCED_MAX_FPS`.
+    pub fn new(max_fps : u32, vsync : bool, now : Instant) -> Self {
+        let max_fps = match max_fps {
+            0 if vsync => None,
+            0 => Some(UNSYNCED_MAX_FPS),
+            max_fps => Some(max_fps),
+        };
+
+        FrameLimiter {
+            interval: max_fps.map(|max_fps| Duration::from_secs(1) / max_fps),
+            next_frame: now,
+        }
+    }
+
+    /// Whether a frame should be drawn now. If it should, the next one is scheduled.
+    pub fn start_frame(&mut self, now : Instant) -> bool {
+        let Some(interval) = self.interval else {
+            return true;
+        };
+
+        if now < self.next_frame {
+            return false;
+        }
+
+        // schedule from the previous deadline to keep a steady rate, unless we've fallen behind
+        self.next_frame = (self.next_frame + interval).max(now);
+        true
+    }
+
+    /// When the event loop should wake up for the next frame, or None to keep polling
+    /// because vsync already blocks each frame until it's due.
+    pub fn wake_time(&self) -> Option<Instant> {
+        self.interval.map(|_| self.next_frame)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MS : Duration = Duration::from_millis(1);
+
+    #[test]
+    fn steps_are_fixed_regardless_of_frame_time() {
+        let mut fast = FixedTimestep::new(50);
+        let mut slow = FixedTimestep::new(50);
+
+        // one second in 5ms frames or in 100ms frames gives the same number of steps
+        let fast_steps: u32 = (0..200).map(|_| fast.advance(5 * MS)).sum();
+        let slow_steps: u32 = (0..10).map(|_| slow.advance(100 * MS)).sum();
+
+        assert_eq!(fast_steps, 50);
+        assert_eq!(slow_steps, 50);
+    }
+
+    #[test]
+    fn leftover_time_becomes_alpha() {
+        let mut timestep = FixedTimestep::new(10);
+
+        assert_eq!(timestep.advance(250 * MS), 2);
+        assert!((timestep.alpha() - 0.5).abs() < 1e-5);
+    }
+
+    #[test]
+    fn long_stalls_are_capped() {
+        let mut timestep = FixedTimestep::new(60);
+
+        assert_eq!(timestep.advance(Duration::from_secs(10)), MAX_STEPS_PER_FRAME);
+        assert_eq!(timestep.alpha(), 0.0);
+    }
+
+    #[test]
+    fn limiter_waits_for_the_next_frame() {
+        let start = Instant::now();
+        let mut limiter = FrameLimiter::new(100, false, start);
+
+        assert!(limiter.start_frame(start));
+        assert!(!limiter.start_frame(start + 4 * MS));
+        assert_eq!(limiter.wake_time(), Some(start + 10 * MS));
+        assert!(limiter.start_frame(start + 10 * MS));
+    }
+
+    #[test]
+    fn uncapped_limiter_leaves_pacing_to_vsync() {
+        let start = Instant::now();
+        let mut limiter = FrameLimiter::new(0, true, start);
+
+        assert!(limiter.start_frame(start));
+        assert!(limiter.start_frame(start));
+        assert_eq!(limiter.wake_time(), None);
+    }
+
+    #[test]
+    fn uncapped_limiter_without_vsync_still_waits() {
+        let start = Instant::now();
+        let mut limiter = FrameLimiter::new(0, false, start);
+
+        assert!(limiter.start_frame(start));
+        assert!(!limiter.start_frame(start));
+        assert_eq!(limiter.wake_time(), Some(start + Duration::from_secs(1) / UNSYNCED_MAX_FPS));
+    }
+}
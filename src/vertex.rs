@@ -0,0 +1,207 @@
+use std::mem;
+use gl::types::{GLenum, GLint, GLsizei, GLuint};
+
+/// How an attribute's components are stored in the vertex buffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AttributeType {
+    Float,
+}
+
+impl AttributeType {
+    fn size(self) -> usize {
+        match self {
+            AttributeType::Float => mem::size_of::<f32>(),
+        }
+    }
+
+    fn gl_type(self) -> GLenum {
+        match self {
+            AttributeType::Float => gl::FLOAT,
+        }
+    }
+
+    // whether `apply` hands it to the shader as an integer, `validate` holds shader inputs to the same rule
+    fn reads_as_integer(self) -> bool {
+        match self {
+            AttributeType::Float => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VertexAttribute {
+    /// The `in` variable the attribute feeds in the vertex shader.
+    pub name : &'static str,
+    pub components : usize,
+    pub kind : AttributeType,
+}
+
+impl VertexAttribute {
+    pub const fn float(name : &'static str, components : usize) -> Self {
+        VertexAttribute {
+            name,
+            components,
+            kind: AttributeType::Float,
+        }
+    }
+}
+
+/// The attributes of one interleaved vertex, in buffer order. Each attribute is bound to the
+/// location matching its index, so VAOs built from a layout work with any program that uses it.
+#[derive(Debug)]
+pub struct VertexLayout {
+    pub attributes : &'static [VertexAttribute],
+}
+
+/// An `in` variable of a linked program, as reported by glGetActiveAttrib.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ActiveAttribute {
+    pub name : String,
+    pub location : GLint,
+    // GL_FLOAT_VEC3 and so on
+    pub gl_type : GLenum,
+}
+
+impl VertexLayout {
+    /// Size of one vertex in bytes.
+    pub fn stride(&self) -> usize {
+        self.attributes.iter().map(|attribute| attribute.components * attribute.kind.size()).sum()
+    }
+
+    pub fn vertex_count<T>(&self, vertices : &[T]) -> GLsizei {
+        (mem::size_of_val(vertices) / self.stride()) as GLsizei
+    }
+
+    /// Has to happen before the program is linked, shaders with an explicit `layout (location = n)`
+    /// have to agree with it.
+    pub unsafe fn bind_locations(&self, program : GLuint) {
+        for (location, attribute) in self.attributes.iter().enumerate() {
+            let name = std::ffi::CString::new(attribute.name).unwrap();
+            gl::BindAttribLocation(program, location as GLuint, name.as_ptr());
+        }
+    }
+
+    /// Checks a linked program reads its inputs the way this layout provides them.
+    /// Attributes the program doesn't use are fine, the compiler drops unused inputs.
+    pub fn validate(&self, active : &[ActiveAttribute]) -> Result<(), String> {
+        for input in active.iter().filter(|input| !input.name.starts_with("gl_")) {
+            let Some(location) = self.attributes.iter().position(|attribute| attribute.name == input.name) else {
+                return Err(format!("the shader reads `{}` but the vertex layout doesn't provide it", input.name));
+            };
+            let attribute = &self.attributes[location];
+
+            if input.location != location as GLint {
+                return Err(format!(
+                    "`{}` is at location {} in the shader but {} in the vertex layout",
+                    input.name, input.location, location
+                ));
+            }
+
+            let Some((components, integer)) = shape_of(input.gl_type) else {
+                return Err(format!("`{}` has a type vertex layouts don't support", input.name));
+            };
+
+            if components != attribute.components {
+                return Err(format!(
+                    "`{}` has {} components in the shader but {} in the vertex layout",
+                    input.name, components, attribute.components
+                ));
+            }
+
+            // integers and floats are bound differently, each has to be read as what it is
+            if integer != attribute.kind.reads_as_integer() {
+                let (shader, layout) = if integer { ("an integer", "a float") } else { ("a float", "an integer") };
+                return Err(format!("`{}` is {} in the shader but {} in the vertex layout", input.name, shader, layout));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Points the bound VAO's attributes at the bound vertex buffer.
+    pub unsafe fn apply(&self) {
+        let stride = self.stride() as GLsizei;
+        let mut offset = 0;
+
+        for (location, attribute) in self.attributes.iter().enumerate() {
+            let location = location as GLuint;
+            let pointer = offset as *const std::ffi::c_void;
+
+            if attribute.kind.reads_as_integer() {
+                gl::VertexAttribIPointer(location, attribute.components as GLint, attribute.kind.gl_type(), stride, pointer);
+            } else {
+                gl::VertexAttribPointer(location, attribute.components as GLint, attribute.kind.gl_type(), gl::FALSE, stride, pointer);
+            }
+            gl::EnableVertexAttribArray(location);
+
+            offset += attribute.components * attribute.kind.size();
+        }
+    }
+}
+
+// component count of a GLSL input type, and whether it's an integer type
+fn shape_of(gl_type : GLenum) -> Option<(usize, bool)> {
+    let shape = match gl_type {
+        gl::FLOAT => (1, false),
+        gl::FLOAT_VEC2 => (2, false),
+        gl::FLOAT_VEC3 => (3, false),
+        gl::FLOAT_VEC4 => (4, false),
+        gl::INT | gl::UNSIGNED_INT => (1, true),
+        gl::INT_VEC2 | gl::UNSIGNED_INT_VEC2 => (2, true),
+        gl::INT_VEC3 | gl::UNSIGNED_INT_VEC3 => (3, true),
+        gl::INT_VEC4 | gl::UNSIGNED_INT_VEC4 => (4, true),
+        _ => return None,
+    };
+
+    Some(shape)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LAYOUT : VertexLayout = VertexLayout {
+        attributes: &[
+            VertexAttribute::float("position", 3),
+            VertexAttribute::float("texture", 2),
+            VertexAttribute::float("colour", 4),
+        ],
+    };
+
+    fn active(name : &str, location : GLint, gl_type : GLenum) -> ActiveAttribute {
+        ActiveAttribute { name: name.to_string(), location, gl_type }
+    }
+
+    #[test]
+    fn stride_adds_up_every_attribute() {
+        assert_eq!(LAYOUT.stride(), (3 + 2 + 4) * 4);
+        assert_eq!(LAYOUT.vertex_count(&[0f32; 9 * 3]), 3);
+    }
+
+    #[test]
+    fn matching_programs_pass() {
+        let program = [
+            active("gl_VertexID", -1, gl::INT),
+            active("position", 0, gl::FLOAT_VEC3),
+            active("colour", 2, gl::FLOAT_VEC4),
+        ];
+
+        // `texture` was optimised out, that's fine
+        assert_eq!(LAYOUT.validate(&program), Ok(()));
+    }
+
+    #[test]
+    fn mismatches_are_explained() {
+        let missing = LAYOUT.validate(&[active("normal", 3, gl::FLOAT_VEC3)]).unwrap_err();
+        assert!(missing.contains("`normal`"), "{}", missing);
+
+        let moved = LAYOUT.validate(&[active("texture", 0, gl::FLOAT_VEC2)]).unwrap_err();
+        assert!(moved.contains("location 0"), "{}", moved);
+
+        let wrong_size = LAYOUT.validate(&[active("position", 0, gl::FLOAT_VEC4)]).unwrap_err();
+        assert!(wrong_size.contains("4 components"), "{}", wrong_size);
+
+        let integer = LAYOUT.validate(&[active("colour", 2, gl::UNSIGNED_INT_VEC4)]).unwrap_err();
+        assert!(integer.contains("an integer in the shader"), "{}", integer);
+    }
+}
@@ -1,18 +1,185 @@
-use cgmath::Vector3;
-use crate::cube::Cube;
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+use std::path::Path;
+use std::sync::Arc;
+use cgmath::Point3;
+use crate::block::{BlockId, AIR};
+use crate::chunk::{Chunk, ChunkPos, CHUNK_SIZE, NEIGHBOUR_OFFSETS};
+use crate::save::{read_world, write_world, SaveError};
+use crate::terrain::{WorldGenerator, WorldType};
 
+/// The currently loaded chunks, `ChunkManager` decides which ones those are.
 pub struct World {
-    pub objects : Vec<Cube>
+    pub chunks : HashMap<ChunkPos, Chunk>,
+    world_type : WorldType,
+    generator : Arc<WorldGenerator>,
+    // chunks that can't be regenerated from the seed and aren't loaded right now
+    stored : HashMap<ChunkPos, Chunk>,
+    // loaded chunks that can't be regenerated from the seed
+    changed : HashSet<ChunkPos>,
 }
 
 impl World {
-    pub fn new() -> Self {
-        let cube = Cube::new(Vector3::new(0.0, 0.0, 0.0));
-        let mut objects = Vec::new();
-        objects.push(cube);
-
+    pub fn new(world_type : WorldType) -> Self {
         World {
-            objects
+            chunks: HashMap::new(),
+            world_type,
+            generator: Arc::new(WorldGenerator::new(world_type)),
+            stored: HashMap::new(),
+            changed: HashSet::new(),
+        }
+    }
+
+    /// Reads a world written by `save`. Chunks come back into view through `take_stored`.
+    pub fn load(directory : &Path) -> Result<Self, SaveError> {
+        let data = read_world(directory)?;
+
+        let mut world = World::new(data.world_type);
+        world.stored = data.chunks;
+
+        Ok(world)
+    }
+
+    /// Writes the world header and every chunk that differs from what the generator would produce.
+    /// Unchanged chunks are left out, the seed in the header regenerates them.
+    pub fn save(&self, directory : &Path) -> Result<(), SaveError> {
+        let chunks: Vec<(ChunkPos, &Chunk)> = self.changed
+            .iter()
+            .filter_map(|pos| self.chunks.get(pos).map(|chunk| (*pos, chunk)))
+            .chain(self.stored.iter().map(|(pos, chunk)| (*pos, chunk)))
+            .collect();
+
+        write_world(directory, self.world_type, &chunks)
+    }
+
+    /// Moves a saved chunk back into the loaded chunks, if there is one for `pos`.
+    /// Returns false when the chunk has to be generated instead.
+    pub fn take_stored(&mut self, pos : ChunkPos) -> bool {
+        match self.stored.remove(&pos) {
+            Some(chunk) => {
+                self.chunks.insert(pos, chunk);
+                self.changed.insert(pos);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Drops a loaded chunk, keeping it in memory only if it can't be regenerated.
+    pub fn unload(&mut self, pos : ChunkPos) {
+        if let Some(chunk) = self.chunks.remove(&pos) {
+            if self.changed.remove(&pos) {
+                self.stored.insert(pos, chunk);
+            }
+        }
+    }
+
+    /// The block at a world position, unloaded chunks read as air.
+    pub fn get_block(&self, x : i32, y : i32, z : i32) -> BlockId {
+        let (chunk_pos, (lx, ly, lz)) = ChunkPos::of_block(x, y, z);
+
+        match self.chunks.get(&chunk_pos) {
+            Some(chunk) => chunk.get(lx, ly, lz),
+            None => AIR,
+        }
+    }
+
+    /// Changes a block in a loaded chunk and returns the chunks whose mesh is now out of date:
+    /// the chunk itself plus any loaded neighbour it shares the block's faces with.
+    pub fn set_block(&mut self, x : i32, y : i32, z : i32, block : BlockId) -> Vec<ChunkPos> {
+        let (chunk_pos, (lx, ly, lz)) = ChunkPos::of_block(x, y, z);
+
+        let Some(chunk) = self.chunks.get_mut(&chunk_pos) else {
+            return Vec::new();
+        };
+        chunk.set(lx, ly, lz, block);
+        self.changed.insert(chunk_pos);
+
+        let mut affected = vec![chunk_pos];
+        let last = CHUNK_SIZE - 1;
+        for (offset, local) in NEIGHBOUR_OFFSETS.iter().zip([lx, lx, ly, ly, lz, lz]) {
+            let on_border = (offset.0 + offset.1 + offset.2 < 0 && local == 0)
+                || (offset.0 + offset.1 + offset.2 > 0 && local == last);
+            let neighbour = chunk_pos.offset(*offset);
+
+            if on_border && self.chunks.contains_key(&neighbour) {
+                affected.push(neighbour);
+            }
         }
+
+        affected
+    }
+
+    /// Shared with the worker threads that generate chunks.
+    pub fn generator(&self) -> Arc<WorldGenerator> {
+        Arc::clone(&self.generator)
+    }
+
+    /// Range of chunk y coordinates that can contain blocks.
+    pub fn chunk_y_range(&self) -> Range<i32> {
+        self.generator.chunk_y_range()
+    }
+
+    /// A spot just above the ground at the world origin.
+    pub fn spawn_point(&self) -> Point3<f32> {
+        let ground = self.generator.surface_height(0, 0);
+        Point3::new(0.5, ground as f32 + 3.0, 3.5)
+    }
+
+    /// The six chunks around `pos`, in the order the mesher expects.
+    pub fn neighbours(&self, pos : ChunkPos) -> [Option<&Chunk>; 6] {
+        NEIGHBOUR_OFFSETS.map(|offset| self.chunks.get(&pos.offset(offset)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::BRICK;
+
+    #[test]
+    fn changed_chunks_survive_save_and_load() {
+        let directory = std::env::temp_dir().join(format!("glutin_demo_world_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&directory);
+
+        let mut world = World::new(WorldType::Terrain { seed: 77 });
+        let edited = ChunkPos::new(2, 0, -1);
+        let untouched = ChunkPos::new(0, 0, 0);
+        let mut chunk = Chunk::default();
+        chunk.set(1, 2, 3, BRICK);
+        world.chunks.insert(edited, chunk);
+        world.changed.insert(edited);
+        world.chunks.insert(untouched, Chunk::default());
+
+        // unloaded edits still have to be written out
+        world.unload(edited);
+        world.save(&directory).unwrap();
+
+        let mut loaded = World::load(&directory).unwrap();
+        assert_eq!(loaded.world_type, WorldType::Terrain { seed: 77 });
+        assert!(!loaded.take_stored(untouched));
+        assert!(loaded.take_stored(edited));
+        assert_eq!(loaded.chunks[&edited].get(1, 2, 3), BRICK);
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn editing_a_border_block_dirties_the_neighbour() {
+        let mut world = World::new(WorldType::Demo);
+        for pos in [ChunkPos::new(0, 0, 0), ChunkPos::new(-1, 0, 0), ChunkPos::new(0, 1, 0)] {
+            world.chunks.insert(pos, Chunk::default());
+        }
+
+        assert_eq!(world.set_block(5, 5, 5, BRICK), vec![ChunkPos::new(0, 0, 0)]);
+        assert_eq!(world.get_block(5, 5, 5), BRICK);
+
+        let mut affected = world.set_block(0, 15, 3, BRICK);
+        affected.sort_by_key(|pos| (pos.x, pos.y, pos.z));
+        assert_eq!(affected, vec![ChunkPos::new(-1, 0, 0), ChunkPos::new(0, 0, 0), ChunkPos::new(0, 1, 0)]);
+
+        // unloaded chunks can't be edited
+        assert!(world.set_block(100, 0, 0, BRICK).is_empty());
+        assert_eq!(world.get_block(100, 0, 0), AIR);
     }
-}
\ No newline at end of file
+}